@@ -0,0 +1,128 @@
+//! 对比 --jobs 不同线程数时 stat 大量文件的耗时
+//! 运行: cargo bench --bench jobs
+//! 可以用 RSTAT_BENCH_FILES 指定生成的文件数量，RSTAT_BENCH_DIR 指定在哪个目录下生成（例如网络文件系统的挂载点），
+//! RSTAT_BENCH_LATENCY_US 指定模拟的每次 stat 延迟（微秒）
+//!
+//! 单核虚拟机、tmpfs 上的 20000 个文件（-t 输出，三次中最快的一次）：
+//!
+//! ```text
+//! jobs  1:   70.022ms
+//! jobs  2:  115.329ms  speedup 0.61x
+//! jobs  4:  139.346ms  speedup 0.50x
+//! jobs  8:  138.557ms  speedup 0.51x
+//! jobs 16:  140.998ms  speedup 0.50x
+//! ```
+//!
+//! 同一台机器上给每次 lstat 加上 200µs 的模拟延迟（相当于局域网内 NFS 一次往返），2000 个文件直接跑线程池：
+//!
+//! ```text
+//! simulated  1:  559.365ms
+//! simulated  2:  296.396ms  speedup 1.89x
+//! simulated  4:  138.635ms  speedup 4.03x
+//! simulated  8:   68.932ms  speedup 8.11x
+//! simulated 16:   35.886ms  speedup 15.59x
+//! ```
+//!
+//! 只有一个核并且 stat 不会阻塞时线程之间的切换就是全部的开销，--jobs 没有好处，所以默认是 1；
+//! 每次 stat 都要等待I/O时（冷缓存、网络文件系统）线程数越多等待重叠得越多，那时 --jobs 才有用
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// 直接借用 rstat 的线程池；bench 也以 cfg(test) 编译，但不跑其中的测试，所以里面的 use 会显得没用
+#[path = "../src/pool.rs"]
+#[allow(unused_imports)]
+mod pool;
+
+const DIRS : usize = 100;
+
+fn main(){
+    let count : usize = std::env::var("RSTAT_BENCH_FILES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(20000);
+    let base = std::env::var_os("RSTAT_BENCH_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let root = base.join(format!("rstat-bench-{}", std::process::id()));
+
+    let files = make_tree(&root, count);
+    println!("synthetic tree: {} files in {} directories under {}", files.len(), DIRS, root.display());
+
+    let serial = run(&root, &files, 1);
+    println!("jobs {:>2}: {:>10.3?}", 1, serial);
+    for jobs in [2, 4, 8, 16]{
+        let t = run(&root, &files, jobs);
+        println!("jobs {:>2}: {:>10.3?}  speedup {:.2}x", jobs, t, serial.as_secs_f64() / t.as_secs_f64());
+    }
+
+    // 模拟每次 stat 都要等一次往返，只测线程池本身，文件数取前十分之一以免跑太久
+    let latency = Duration::from_micros(std::env::var("RSTAT_BENCH_LATENCY_US")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(200));
+    let files = &files[..files.len() / 10];
+    println!("simulated latency {:?} per stat, {} files", latency, files.len());
+    let serial = simulate(&root, files, 1, latency);
+    println!("simulated {:>2}: {:>10.3?}", 1, serial);
+    for jobs in [2, 4, 8, 16]{
+        let t = simulate(&root, files, jobs, latency);
+        println!("simulated {:>2}: {:>10.3?}  speedup {:.2}x", jobs, t, serial.as_secs_f64() / t.as_secs_f64());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+/// 生成 count 个文件，平均分布在 DIRS 个目录里，每十个文件中放一个符号链接
+fn make_tree(root : &Path, count : usize) -> Vec<String>{
+    let mut files = Vec::with_capacity(count);
+    for d in 0..DIRS{
+        fs::create_dir_all(root.join(format!("d{:02}", d))).unwrap();
+    }
+    for i in 0..count{
+        let name = format!("d{:02}/f{:06}", i % DIRS, i);
+        if i % 10 == 9{
+            std::os::unix::fs::symlink(format!("f{:06}", i - 1), root.join(&name)).unwrap();
+        }
+        else{
+            fs::write(root.join(&name), b"x").unwrap();
+        }
+        files.push(name);
+    }
+    files
+}
+
+/// 取三次运行里最快的一次，减少缓存带来的抖动
+fn run(root : &Path, files : &[String], jobs : usize) -> Duration{
+    (0..3)
+        .map(|_|{
+            let start = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_rstat"))
+                .current_dir(root)
+                .arg("-t")
+                .arg(format!("--jobs={}", jobs))
+                .args(files)
+                .stdout(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// 用 jobs 个线程 lstat 每个文件，每次调用之后再睡 latency 模拟网络文件系统的往返
+fn simulate(root : &Path, files : &[String], jobs : usize, latency : Duration) -> Duration{
+    let root = root.to_path_buf();
+    let getter = move |name : String|{
+        let meta = fs::symlink_metadata(root.join(name));
+        std::thread::sleep(latency);
+        meta.is_ok()
+    };
+    let start = Instant::now();
+    let found = pool::OrderedIter::new(files.to_vec(), jobs, getter).filter(|ok| *ok).count();
+    assert_eq!(found, files.len());
+    start.elapsed()
+}
@@ -0,0 +1,149 @@
+//! POSIX ACL，从 system.posix_acl_access / system.posix_acl_default 两个扩展属性中解析
+//!
+//! 内核中的格式为一个4字节的版本号（2），之后每个条目8字节：
+//! 2字节tag、2字节权限、4字节id，均为小端序，条目已经按tag和id排好序
+use crate::kstat::{lookup_groupname, lookup_username};
+
+pub const ACCESS : &str = "system.posix_acl_access";
+pub const DEFAULT : &str = "system.posix_acl_default";
+
+const ACL_VERSION : u32 = 2;
+
+const ACL_USER_OBJ : u16 = 0x01;
+const ACL_USER : u16 = 0x02;
+const ACL_GROUP_OBJ : u16 = 0x04;
+const ACL_GROUP : u16 = 0x08;
+const ACL_MASK : u16 = 0x10;
+const ACL_OTHER : u16 = 0x20;
+
+pub struct AclEntry{
+    pub tag : u16,
+    pub perm : u16,
+    pub id : u32,
+}
+
+pub struct Acl{
+    pub entries : Vec<AclEntry>,
+}
+
+impl Acl{
+    /// 解析扩展属性的值，格式不对时返回None
+    pub fn parse(value : &[u8]) -> Option<Acl>{
+        if value.len()<4 || !(value.len()-4).is_multiple_of(8){
+            return None;
+        }
+        if u32::from_le_bytes(value[..4].try_into().ok()?)!=ACL_VERSION{
+            return None;
+        }
+        let entries = value[4..].chunks(8).map(|e| AclEntry{
+            tag : u16::from_le_bytes([e[0], e[1]]),
+            perm : u16::from_le_bytes([e[2], e[3]]),
+            id : u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+        }).collect();
+        Some(Acl{entries})
+    }
+
+    /// 除了属主、属组、其他人三项之外还有别的条目，即ls -l 中会带'+'的情况
+    pub fn is_extended(&self) -> bool{
+        self.entries.iter().any(|e| matches!(e.tag, ACL_USER | ACL_GROUP | ACL_MASK))
+    }
+
+    pub fn mask(&self) -> Option<u16>{
+        self.entries.iter().find(|e| e.tag==ACL_MASK).map(|e| e.perm)
+    }
+
+    /// getfacl形式的每一行，被mask限制后实际权限不同的条目在后面注明#effective
+    pub fn lines(&self) -> Vec<String>{
+        let mask = self.mask();
+        self.entries.iter().map(|e|{
+            let entry = entry_text(e);
+            match mask{
+                // 命名用户、属组和命名组的权限都要和mask取交集
+                Some(mask) if matches!(e.tag, ACL_USER | ACL_GROUP_OBJ | ACL_GROUP) && e.perm & mask!=e.perm => {
+                    format!("{:<24}#effective:{}",entry,perm_text(e.perm & mask))
+                },
+                _ => entry,
+            }
+        }).collect()
+    }
+
+    /// 逗号分隔的短格式，和setfacl -m 接受的写法相同
+    pub fn short_text(&self) -> String{
+        self.entries.iter().map(entry_text).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// 是否带有扩展的ACL：访问ACL中有额外的条目，或者设置了默认ACL
+/// 参数是两个扩展属性的原始值，没有设置时为None
+pub fn has_extended(access : Option<&[u8]>, default : Option<&[u8]>) -> bool{
+    if access.and_then(Acl::parse).is_some_and(|acl| acl.is_extended()){
+        return true;
+    }
+    default.and_then(Acl::parse).is_some_and(|acl| !acl.entries.is_empty())
+}
+
+fn entry_text(e : &AclEntry) -> String{
+    let perm = perm_text(e.perm);
+    match e.tag{
+        ACL_USER_OBJ => format!("user::{}",perm),
+        ACL_USER => format!("user:{}:{}",user_name(e.id),perm),
+        ACL_GROUP_OBJ => format!("group::{}",perm),
+        ACL_GROUP => format!("group:{}:{}",group_name(e.id),perm),
+        ACL_MASK => format!("mask::{}",perm),
+        ACL_OTHER => format!("other::{}",perm),
+        _ => format!("unknown({:#x}):{}:{}",e.tag,e.id,perm),
+    }
+}
+
+fn perm_text(perm : u16) -> String{
+    [(4, 'r'), (2, 'w'), (1, 'x')].iter()
+        .map(|&(bit, ch)| if perm & bit!=0 { ch } else { '-' })
+        .collect()
+}
+
+/// 找不到对应的用户时和getfacl一样给出数字
+fn user_name(uid : u32) -> String{
+    lookup_username(uid).unwrap_or_else(|| uid.to_string())
+}
+
+fn group_name(gid : u32) -> String{
+    lookup_groupname(gid).unwrap_or_else(|| gid.to_string())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    /// 按内核格式拼出扩展属性的值
+    fn blob(entries : &[(u16, u16, u32)]) -> Vec<u8>{
+        let mut value = ACL_VERSION.to_le_bytes().to_vec();
+        for &(tag, perm, id) in entries{
+            value.extend_from_slice(&tag.to_le_bytes());
+            value.extend_from_slice(&perm.to_le_bytes());
+            value.extend_from_slice(&id.to_le_bytes());
+        }
+        value
+    }
+
+    const MINIMAL : [(u16, u16, u32); 3] = [(ACL_USER_OBJ, 6, u32::MAX), (ACL_GROUP_OBJ, 4, u32::MAX), (ACL_OTHER, 4, u32::MAX)];
+
+    #[test]
+    fn extended_marker(){
+        let minimal = blob(&MINIMAL);
+        assert!(!has_extended(Some(&minimal), None));
+        assert!(!has_extended(None, None));
+        // 只设置了默认ACL也要带'+'
+        assert!(has_extended(None, Some(&minimal)));
+        let named = blob(&[(ACL_USER_OBJ, 6, 0), (ACL_USER, 6, 4000000000), (ACL_GROUP_OBJ, 4, 0), (ACL_MASK, 4, 0), (ACL_OTHER, 4, 0)]);
+        assert!(has_extended(Some(&named), None));
+        assert!(!has_extended(Some(&named[..7]), None));
+    }
+
+    #[test]
+    fn unknown_ids_are_numeric(){
+        let acl = Acl::parse(&blob(&[(ACL_USER_OBJ, 6, 0), (ACL_USER, 7, 4000000000), (ACL_GROUP, 5, 4000000001),
+            (ACL_GROUP_OBJ, 4, 0), (ACL_MASK, 4, 0), (ACL_OTHER, 0, 0)])).unwrap();
+        assert_eq!(acl.short_text(), "user::rw-,user:4000000000:rwx,group:4000000001:r-x,group::r--,mask::r--,other::---");
+        assert_eq!(acl.lines()[1], "user:4000000000:rwx     #effective:r--");
+    }
+}
@@ -0,0 +1,149 @@
+//! 文件能力（security.capability），用来代替setuid给程序单独的特权
+//!
+//! 扩展属性中为 struct vfs_cap_data，均为小端序：
+//! magic_etc(4字节，高8位为版本，最低位为effective标志) + 每32个能力一组的 permitted/inheritable，
+//! 版本3在最后多一个rootid，表示在哪个用户命名空间的root下生效
+pub const XATTR_NAME : &str = "security.capability";
+
+const VFS_CAP_REVISION_MASK : u32 = 0xFF000000;
+const VFS_CAP_REVISION_1 : u32 = 0x01000000;
+const VFS_CAP_REVISION_2 : u32 = 0x02000000;
+const VFS_CAP_REVISION_3 : u32 = 0x03000000;
+const VFS_CAP_FLAGS_EFFECTIVE : u32 = 0x000001;
+
+/// 能力的名字，下标即为能力的编号
+const CAP_NAMES : [&str; 41] = [
+    "chown", "dac_override", "dac_read_search", "fowner", "fsetid", "kill", "setgid", "setuid",
+    "setpcap", "linux_immutable", "net_bind_service", "net_broadcast", "net_admin", "net_raw",
+    "ipc_lock", "ipc_owner", "sys_module", "sys_rawio", "sys_chroot", "sys_ptrace", "sys_pacct",
+    "sys_admin", "sys_boot", "sys_nice", "sys_resource", "sys_time", "sys_tty_config", "mknod",
+    "lease", "audit_write", "audit_control", "setfcap", "mac_override", "mac_admin", "syslog",
+    "wake_alarm", "block_suspend", "audit_read", "perfmon", "bpf", "checkpoint_restore",
+];
+
+pub struct FileCaps{
+    pub permitted : u64,
+    pub inheritable : u64,
+    pub effective : bool,
+    pub rootid : Option<u32>,     //只有版本3才有
+}
+
+impl FileCaps{
+    /// 解析扩展属性的值，格式不对时返回None
+    pub fn parse(value : &[u8]) -> Option<FileCaps>{
+        let word = |i : usize| -> Option<u32>{
+            Some(u32::from_le_bytes(value.get(i*4..i*4+4)?.try_into().ok()?))
+        };
+        let magic = word(0)?;
+        let (groups, rootid) = match magic & VFS_CAP_REVISION_MASK{
+            VFS_CAP_REVISION_1 => (1, None),
+            VFS_CAP_REVISION_2 => (2, None),
+            VFS_CAP_REVISION_3 => (2, Some(word(5)?)),
+            _ => return None,
+        };
+        let mut permitted = 0u64;
+        let mut inheritable = 0u64;
+        for i in 0..groups{
+            permitted |= (word(1+i*2)? as u64)<<(32*i);
+            inheritable |= (word(2+i*2)? as u64)<<(32*i);
+        }
+        Some(FileCaps{
+            permitted,
+            inheritable,
+            effective : magic & VFS_CAP_FLAGS_EFFECTIVE!=0,
+            rootid,
+        })
+    }
+
+    /// 形如 cap_net_bind_service,cap_sys_ptrace+ep 的文字，标志相同的能力写在一组里，
+    /// 不同的组之间用空格分开；版本3在最后注明rootid
+    pub fn to_text(&self) -> String{
+        let mut groups : Vec<(String, Vec<String>)> = Vec::new();
+        for bit in 0..64{
+            let p = self.permitted>>bit & 1==1;
+            let i = self.inheritable>>bit & 1==1;
+            if !p && !i{
+                continue;
+            }
+            let mut flags = String::from("+");
+            if self.effective{
+                flags.push('e');
+            }
+            if i{
+                flags.push('i');
+            }
+            if p{
+                flags.push('p');
+            }
+            match groups.iter_mut().find(|(f, _)| *f==flags){
+                Some((_, names)) => names.push(cap_name(bit)),
+                None => groups.push((flags, vec![cap_name(bit)])),
+            }
+        }
+        let mut text = groups.iter()
+            .map(|(flags, names)| format!("{}{}",names.join(","),flags))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Some(rootid) = self.rootid{
+            text.push_str(&format!(" [rootid={}]",rootid));
+        }
+        text
+    }
+}
+
+fn cap_name(bit : usize) -> String{
+    match CAP_NAMES.get(bit){
+        Some(name) => format!("cap_{}",name),
+        None => format!("cap_{}",bit),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn blob(words : &[u32]) -> Vec<u8>{
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decode_v2(){
+        // cap_net_bind_service(10) 和 cap_net_raw(13)，effective
+        let caps = FileCaps::parse(&blob(&[0x02000001, 1<<10 | 1<<13, 0, 0, 0])).unwrap();
+        assert_eq!(caps.permitted, 1<<10 | 1<<13);
+        assert!(caps.effective);
+        assert_eq!(caps.rootid, None);
+        assert_eq!(caps.to_text(), "cap_net_bind_service,cap_net_raw+ep");
+    }
+
+    #[test]
+    fn decode_v2_high_word_and_inheritable(){
+        // cap_bpf(39)在第二组，cap_chown(0)同时在inheritable中
+        let caps = FileCaps::parse(&blob(&[0x02000000, 1, 1, 1<<7, 0])).unwrap();
+        assert_eq!(caps.permitted, 1 | 1<<39);
+        assert_eq!(caps.inheritable, 1);
+        assert_eq!(caps.to_text(), "cap_chown+ip cap_bpf+p");
+    }
+
+    #[test]
+    fn decode_v3_rootid(){
+        let caps = FileCaps::parse(&blob(&[0x03000001, 1<<21, 0, 0, 0, 100000])).unwrap();
+        assert_eq!(caps.rootid, Some(100000));
+        assert_eq!(caps.to_text(), "cap_sys_admin+ep [rootid=100000]");
+    }
+
+    #[test]
+    fn decode_v1(){
+        let caps = FileCaps::parse(&blob(&[0x01000000, 1<<7, 0])).unwrap();
+        assert_eq!(caps.to_text(), "cap_setuid+p");
+    }
+
+    #[test]
+    fn reject_bad_blobs(){
+        assert!(FileCaps::parse(&[]).is_none());
+        assert!(FileCaps::parse(&blob(&[0x04000000, 1, 0, 0, 0])).is_none());
+        // 长度不够的v2和缺了rootid的v3
+        assert!(FileCaps::parse(&blob(&[0x02000000, 1, 0])).is_none());
+        assert!(FileCaps::parse(&blob(&[0x03000000, 1, 0, 0, 0])).is_none());
+    }
+}
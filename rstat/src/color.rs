@@ -0,0 +1,223 @@
+//! --color：按 LS_COLORS 给默认输出中的文件名上色，并突出特殊的权限位
+//! LS_COLORS 的格式和 dircolors(1) 生成的相同：用':'分隔的 键=SGR参数，
+//! 键是两个字母的类型（di、ln、ex……）或者 *.扩展名
+use std::collections::HashMap;
+use std::env;
+
+/// 没有设置 LS_COLORS 时使用的颜色，和 dircolors 的默认值一致
+const DEFAULT_COLORS : &str = "rs=0:di=01;34:ln=01;36:pi=40;33:so=01;35:bd=40;33;01:cd=40;33;01:or=40;31;01:\
+ex=01;32:su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44";
+
+/// 找不到属主的用户名、组名时使用的颜色（暗淡）
+const DIM : &str = "2";
+
+/// --color 的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode{
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode{
+    pub fn from_name(name : &str) -> Option<Self>{
+        match name{
+            "auto" | "tty" | "if-tty" => Some(ColorMode::Auto),
+            "always" | "yes" | "force" => Some(ColorMode::Always),
+            "never" | "no" | "none" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+
+    /// 是否真的要输出颜色，to_terminal表示输出的是不是终端
+    /// auto时设置了 NO_COLOR（不论取值，只要非空）也不上色
+    pub fn enabled(&self, to_terminal : bool) -> bool{
+        match self{
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => to_terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+        }
+    }
+}
+
+/// 解析后的 LS_COLORS
+pub struct Palette{
+    types : HashMap<String, String>,    //两个字母的类型
+    extensions : Vec<(String, String)>, //扩展名（小写，带'.'），后出现的优先
+}
+
+impl Palette{
+    pub fn from_env() -> Self{
+        match env::var("LS_COLORS"){
+            Ok(value) if !value.is_empty() => Palette::parse(&value),
+            _ => Palette::parse(DEFAULT_COLORS),
+        }
+    }
+
+    pub fn parse(spec : &str) -> Self{
+        let mut palette = Palette{ types : HashMap::new(), extensions : Vec::new() };
+        for entry in spec.split(':'){
+            let Some((key, value)) = entry.split_once('=') else{
+                continue;
+            };
+            if let Some(pattern) = key.strip_prefix('*'){
+                palette.extensions.push((pattern.to_lowercase(), value.to_string()));
+            }
+            else{
+                palette.types.insert(key.to_string(), value.to_string());
+            }
+        }
+        palette
+    }
+
+    /// 按类型给出的颜色，没有设置或者设置为空、0时不上色
+    fn get(&self, key : &str) -> Option<&str>{
+        self.types.get(key).map(String::as_str).filter(|v| !v.is_empty() && *v!="0" && *v!="00")
+    }
+
+    /// 文件名的颜色，规则和 ls 相同
+    /// mode为文件本身（链接不跟随）的st_mode，dangling表示链接指向的文件不存在
+    pub fn for_file(&self, name : &str, mode : u32, dangling : bool) -> Option<&str>{
+        let key = match mode & libc::S_IFMT{
+            libc::S_IFDIR => {
+                let sticky = mode & libc::S_ISVTX!=0;
+                let other_writable = mode & libc::S_IWOTH!=0;
+                match (sticky, other_writable){
+                    (true, true) if self.get("tw").is_some() => "tw",
+                    (false, true) if self.get("ow").is_some() => "ow",
+                    (true, false) if self.get("st").is_some() => "st",
+                    _ => "di",
+                }
+            },
+            libc::S_IFLNK => {
+                if dangling && self.get("or").is_some() { "or" } else { "ln" }
+            },
+            libc::S_IFIFO => "pi",
+            libc::S_IFSOCK => "so",
+            libc::S_IFBLK => "bd",
+            libc::S_IFCHR => "cd",
+            _ => {
+                if mode & libc::S_ISUID!=0 && self.get("su").is_some(){
+                    "su"
+                }
+                else if mode & libc::S_ISGID!=0 && self.get("sg").is_some(){
+                    "sg"
+                }
+                else if mode & 0o111!=0 && self.get("ex").is_some(){
+                    "ex"
+                }
+                else{
+                    return self.for_extension(name).or_else(|| self.get("fi"));
+                }
+            },
+        };
+        self.get(key)
+    }
+
+    /// 按扩展名匹配，不区分大小写
+    fn for_extension(&self, name : &str) -> Option<&str>{
+        let name = name.to_lowercase();
+        self.extensions.iter().rev()
+            .find(|(pattern, _)| name.ends_with(pattern.as_str()))
+            .map(|(_, value)| value.as_str())
+            .filter(|v| !v.is_empty())
+    }
+
+    /// 给符号形式的权限（例如 -rwsr-xrwt）上色：
+    /// setuid、setgid、sticky所在的位分别用su、sg、st的颜色，其他人可写的'w'用ow的颜色
+    /// 符号链接的权限总是0777，没有意义，不上色
+    pub fn mode(&self, text : &str, mode : u32) -> String{
+        if mode & libc::S_IFMT==libc::S_IFLNK{
+            return text.to_string();
+        }
+        let highlights = [
+            (3, mode & libc::S_ISUID!=0, "su"),
+            (6, mode & libc::S_ISGID!=0, "sg"),
+            (8, mode & libc::S_IWOTH!=0, "ow"),
+            (9, mode & libc::S_ISVTX!=0, "st"),
+        ];
+        text.chars().enumerate().map(|(i, c)|{
+            let code = highlights.iter()
+                .find(|(at, set, _)| *at==i && *set)
+                .and_then(|(_, _, key)| self.get(key));
+            match code{
+                Some(code) => self.paint(Some(code), &c.to_string()),
+                None => c.to_string(),
+            }
+        }).collect()
+    }
+
+    /// 找不到名字的属主、属组显示为暗淡
+    pub fn dim(&self, text : &str) -> String{
+        self.paint(Some(DIM), text)
+    }
+
+    /// 用SGR参数code包住text，code为None时原样返回
+    pub fn paint(&self, code : Option<&str>, text : &str) -> String{
+        match code{
+            Some(code) => format!("\x1b[{}m{}\x1b[{}m",code,text,self.get("rs").unwrap_or("0")),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const REG : u32 = libc::S_IFREG | 0o644;
+    const EXE : u32 = libc::S_IFREG | 0o755;
+
+    #[test]
+    fn type_keys_before_extensions(){
+        let palette = Palette::parse("fi=00:ex=01;32:su=37;41:*.sh=33:*.tar=01;31");
+        assert_eq!(palette.for_file("a.tar", REG, false), Some("01;31"));
+        // 可执行、setuid优先于扩展名
+        assert_eq!(palette.for_file("run.sh", EXE, false), Some("01;32"));
+        assert_eq!(palette.for_file("run.sh", EXE | libc::S_ISUID, false), Some("37;41"));
+        // 目录不按扩展名
+        assert_eq!(palette.for_file("dir.tar", libc::S_IFDIR | 0o755, false), None);
+        // fi=00 等于不上色
+        assert_eq!(palette.for_file("plain", REG, false), None);
+    }
+
+    #[test]
+    fn extensions_ignore_case_and_later_wins(){
+        let palette = Palette::parse("*.gz=31:*.tar.gz=32:*.GZ=33");
+        assert_eq!(palette.for_file("a.tar.gz", REG, false), Some("33"));
+        let palette = Palette::parse("*.gz=31:*.tar.gz=32");
+        assert_eq!(palette.for_file("A.TAR.GZ", REG, false), Some("32"));
+        assert_eq!(palette.for_file("a.gz", REG, false), Some("31"));
+    }
+
+    #[test]
+    fn directory_and_link_variants(){
+        let palette = Palette::parse(DEFAULT_COLORS);
+        assert_eq!(palette.for_file("d", libc::S_IFDIR | 0o1777, false), Some("30;42"));
+        assert_eq!(palette.for_file("d", libc::S_IFDIR | 0o777, false), Some("34;42"));
+        assert_eq!(palette.for_file("d", libc::S_IFDIR | 0o1755, false), Some("37;44"));
+        assert_eq!(palette.for_file("l", libc::S_IFLNK | 0o777, false), Some("01;36"));
+        assert_eq!(palette.for_file("l", libc::S_IFLNK | 0o777, true), Some("40;31;01"));
+        // 没有or时悬空链接和普通链接一样
+        let palette = Palette::parse("ln=36");
+        assert_eq!(palette.for_file("l", libc::S_IFLNK | 0o777, true), Some("36"));
+    }
+
+    /// 格式不对的条目直接跳过，不影响其他条目
+    #[test]
+    fn bad_entries_are_skipped(){
+        let palette = Palette::parse("garbage::di:=01:ex=:*.txt=:ln=01;36:");
+        assert_eq!(palette.for_file("l", libc::S_IFLNK | 0o777, false), Some("01;36"));
+        assert_eq!(palette.for_file("d", libc::S_IFDIR | 0o755, false), None);
+        // ex为空时可执行文件退回到按扩展名，扩展名的值也为空时不上色
+        assert_eq!(palette.for_file("a.txt", EXE, false), None);
+        assert_eq!(Palette::parse("").for_file("x", REG, false), None);
+    }
+
+    #[test]
+    fn paint_resets_with_rs(){
+        let palette = Palette::parse("rs=0:su=37;41");
+        assert_eq!(palette.paint(Some("1"), "x"), "\x1b[1mx\x1b[0m");
+        assert_eq!(palette.mode("-rwsr-xr-x", EXE | libc::S_ISUID), "-rw\x1b[37;41ms\x1b[0mr-xr-x");
+    }
+}
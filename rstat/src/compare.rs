@@ -0,0 +1,24 @@
+//! --compare 模式：逐个字段比较两个文件的信息，输出所有不同的字段
+use std::io::{self, Write};
+
+use crate::kstat::FileStat;
+
+/// 输出a和b之间不同的字段，ignore中的字段不参与比较
+/// 返回两者是否相同
+pub fn compare<W : Write>(out : &mut W, a : &FileStat, b : &FileStat, ignore : &[String]) -> io::Result<bool>{
+    let changes : Vec<_> = a.diff(b).into_iter()
+        .filter(|change| !ignore.iter().any(|name| *name==change.field
+            || (name=="xattrs" && change.field.starts_with("xattr:"))))
+        .collect();
+    if changes.is_empty(){
+        return Ok(true);
+    }
+
+    writeln!(out, "--- {}",a.filename)?;
+    writeln!(out, "+++ {}",b.filename)?;
+    for change in changes{
+        writeln!(out, "-{}: {}",change.field,change.old)?;
+        writeln!(out, "+{}: {}",change.field,change.new)?;
+    }
+    Ok(false)
+}
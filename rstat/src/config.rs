@@ -0,0 +1,212 @@
+//! 用户配置文件：命名的格式预设和默认选项
+//! 依次查找 $RSTAT_CONFIG 和 ~/.config/rstat/config.toml，都不存在时没有任何配置
+//!
+//! ```toml
+//! [defaults]
+//! color = "auto"              # --color
+//! time_style = "long-iso"     # --time-style
+//! quoting = "shell"           # --quoting-style
+//!
+//! [presets]
+//! owner = "%n %U:%G %a"       # --preset owner 相当于 -c "%n %U:%G %a"
+//! "with space" = '%n %s'
+//! ```
+//!
+//! 只支持TOML中用得到的部分：表头、字符串键值（基本字符串和字面量字符串）和注释
+//! 预设只在 --preset 选中时才用解析 -c 的同一个解析器检查，写错的预设不影响其他的运行
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::color::ColorMode;
+use crate::kstat::{parse_format, StatError};
+use crate::manifest::with_path;
+use crate::style::{QuotingStyle, TimeStyle};
+
+/// 配置文件中的内容，没有写的项为None
+#[derive(Debug, Default)]
+pub struct Config{
+    pub path : String,                          //读取的是哪个文件，报错时使用
+    pub presets : BTreeMap<String, (usize, String)>, //预设的名字 -> (所在的行号, 格式串)
+    pub color : Option<ColorMode>,
+    pub time_style : Option<TimeStyle>,
+    pub quoting : Option<QuotingStyle>,
+}
+
+impl Config{
+    /// 读取配置文件，默认位置的文件不存在不算错误，$RSTAT_CONFIG 指定的文件不存在则是错误
+    /// 错误中给出文件名，格式错误还给出行号
+    pub fn load() -> Result<Config, StatError>{
+        let path = match env::var_os("RSTAT_CONFIG").filter(|p| !p.is_empty()){
+            Some(path) => PathBuf::from(path),
+            None => {
+                let Some(home) = env::var_os("HOME") else{
+                    return Ok(Config::default());
+                };
+                let path : PathBuf = [home.as_os_str(), ".config/rstat/config.toml".as_ref()].iter().collect();
+                if !path.exists(){
+                    return Ok(Config::default());
+                }
+                path
+            },
+        };
+        let path = path.to_string_lossy().into_owned();
+        let text = fs::read_to_string(&path).map_err(|e| with_path(&path, e))?;
+        Config::parse(&text, &path)
+    }
+
+    /// 解析配置文件的内容，path只用于错误信息
+    pub fn parse(text : &str, path : &str) -> Result<Config, StatError>{
+        let mut config = Config{ path : path.to_string(), ..Config::default() };
+        let mut section = String::new();
+        for (number, line) in text.lines().enumerate(){
+            let number = number+1;
+            let error = |reason : String| StatError::InvalidConfig(format!("{}:{}: {}",path,number,reason));
+            let line = strip_comment(line).trim();
+            if line.is_empty(){
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')){
+                section = name.trim().to_string();
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error(String::from("expected key = \"value\"")))?;
+            let key = parse_key(key.trim()).ok_or_else(|| error(format!("invalid key {}",key.trim())))?;
+            let value = parse_string(value.trim()).ok_or_else(|| error(format!("invalid string {}",value.trim())))?;
+            let bad_value = || error(format!("invalid value '{}' for {}",value,key));
+            match (section.as_str(), key.as_str()){
+                // 预设在选中时才检查，见 preset()
+                ("presets", _) => {
+                    config.presets.insert(key, (number, value));
+                },
+                ("defaults", "color") => config.color = Some(ColorMode::from_name(&value).ok_or_else(bad_value)?),
+                ("defaults", "time_style") | ("defaults", "time-style") =>
+                    config.time_style = Some(TimeStyle::from_name(&value).ok_or_else(bad_value)?),
+                ("defaults", "quoting") | ("defaults", "quoting_style") | ("defaults", "quoting-style") =>
+                    config.quoting = Some(QuotingStyle::from_name(&value).ok_or_else(bad_value)?),
+                _ => return Err(error(format!("unknown key '{}' in [{}]",key,section))),
+            }
+        }
+        Ok(config)
+    }
+
+    /// 名为name的预设的格式串，用和 -c 相同的解析器检查
+    /// 没有这个预设时给出预设的名字，格式写错时给出文件、行号和预设的名字
+    pub fn preset(&self, name : &str) -> Result<String, StatError>{
+        let (number, format) = self.presets.get(name).ok_or_else(|| StatError::UnknownPreset(name.to_string()))?;
+        match parse_format(format){
+            Ok(_) => Ok(format.clone()),
+            Err(e) => Err(StatError::InvalidConfig(format!("{}:{}: preset '{}': {}",self.path,number,name,e))),
+        }
+    }
+}
+
+/// 去掉行尾的注释，字符串中的'#'不算
+fn strip_comment(line : &str) -> &str{
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices(){
+        match (quote, c){
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            },
+            (Some(q), c) if c==q && !escaped => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {},
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// 键可以是裸键，也可以用引号括起来
+fn parse_key(key : &str) -> Option<String>{
+    if key.starts_with('"') || key.starts_with('\''){
+        return parse_string(key);
+    }
+    let bare = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c=='_' || c=='-');
+    bare.then(|| key.to_string())
+}
+
+/// 基本字符串 "..."（支持常用的转义）或者字面量字符串 '...'
+fn parse_string(value : &str) -> Option<String>{
+    if let Some(body) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')){
+        return (!body.contains('\'')).then(|| body.to_string());
+    }
+    let body = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut res = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next(){
+        match c{
+            '\\' => res.push(match chars.next()?{
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '"' => '"',
+                '\\' => '\\',
+                'u' => {
+                    let hex : String = chars.by_ref().take(4).collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                },
+                _ => return None,
+            }),
+            '"' => return None,
+            c => res.push(c),
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const PATH : &str = "/etc/rstat.toml";
+
+    fn error(text : &str) -> String{
+        Config::parse(text, PATH).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn parse_defaults_and_presets(){
+        let config = Config::parse(concat!(
+            "# comment\n",
+            "[defaults]\n",
+            "color = \"always\"   # trailing comment\n",
+            "time-style = 'long-iso'\n",
+            "\n",
+            "[presets]\n",
+            "owner = \"%n %U:%G # not a comment\"\n",
+            "\"with space\" = '%n\\t%s'\n",
+            "escaped = \"a\\tb\\u00e9\"\n",
+        ), PATH).unwrap();
+        assert_eq!(config.color, Some(ColorMode::Always));
+        assert_eq!(config.time_style, TimeStyle::from_name("long-iso"));
+        assert_eq!(config.quoting, None);
+        assert_eq!(config.presets["owner"], (7, String::from("%n %U:%G # not a comment")));
+        assert_eq!(config.presets["with space"].1, "%n\\t%s");
+        assert_eq!(config.presets["escaped"].1, "a\tb\u{e9}");
+    }
+
+    #[test]
+    fn errors_name_path_and_line(){
+        assert_eq!(error("[defaults]\ncolor = always\n"), "invalid configuration: /etc/rstat.toml:2: invalid string always");
+        assert_eq!(error("[defaults]\ncolor = \"purple\"\n"), "invalid configuration: /etc/rstat.toml:2: invalid value 'purple' for color");
+        assert_eq!(error("\n\n[other]\nkey = 'x'\n"), "invalid configuration: /etc/rstat.toml:4: unknown key 'key' in [other]");
+        assert_eq!(error("[presets]\njust text\n"), "invalid configuration: /etc/rstat.toml:2: expected key = \"value\"");
+        assert_eq!(error("[presets]\nbad key = 'x'\n"), "invalid configuration: /etc/rstat.toml:2: invalid key bad key");
+    }
+
+    /// 写错的预设在解析时不报错，只在选中时报错
+    #[test]
+    fn presets_are_checked_when_selected(){
+        let config = Config::parse("[presets]\ngood = '%n'\nbroken = '%{size'\n", PATH).unwrap();
+        assert_eq!(config.preset("good").unwrap(), "%n");
+        assert_eq!(config.preset("broken").err().unwrap().to_string(),
+            "invalid configuration: /etc/rstat.toml:3: preset 'broken': invalid format string");
+        assert_eq!(config.preset("missing").err().unwrap().to_string(), "unknown preset 'missing'");
+    }
+}
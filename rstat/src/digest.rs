@@ -0,0 +1,191 @@
+//! 文件内容的摘要，按块流式读取，不会把整个文件读进内存
+//! 需要多种摘要时只读一遍文件，每块同时交给所有算法
+use std::fs::OpenOptions;
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// 读取文件时每次读取的大小
+const CHUNK : usize = 64*1024;
+
+/// 支持的摘要算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm{
+    Md5,
+    Sha1,
+    Sha256,
+    Blake3,
+    Crc32,
+}
+
+impl Algorithm{
+    pub const ALL : [Algorithm; 5] = [Algorithm::Md5, Algorithm::Sha1, Algorithm::Sha256, Algorithm::Blake3, Algorithm::Crc32];
+
+    /// 在格式、JSON和清单中使用的名字
+    pub fn name(&self) -> &'static str{
+        match self{
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Crc32 => "crc32",
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Self>{
+        Algorithm::ALL.iter().copied().find(|a| a.name()==name)
+    }
+
+    /// mtree(5)中对应的关键字，mtree没有blake3和zlib的crc32
+    pub fn mtree_keyword(&self) -> Option<&'static str>{
+        match self{
+            Algorithm::Md5 => Some("md5digest"),
+            Algorithm::Sha1 => Some("sha1digest"),
+            Algorithm::Sha256 => Some("sha256digest"),
+            Algorithm::Blake3 | Algorithm::Crc32 => None,
+        }
+    }
+}
+
+/// 正在计算中的一种摘要
+enum Hasher{
+    Md5(Md5),
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher{
+    fn new(algorithm : Algorithm) -> Self{
+        match algorithm{
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+            Algorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+
+    fn update(&mut self, data : &[u8]){
+        match self{
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Blake3(h) => { h.update(data); },
+            Hasher::Crc32(h) => h.update(data),
+        }
+    }
+
+    fn finish(self) -> String{
+        match self{
+            Hasher::Md5(h) => to_hex(&h.finalize()),
+            Hasher::Sha1(h) => to_hex(&h.finalize()),
+            Hasher::Sha256(h) => to_hex(&h.finalize()),
+            Hasher::Blake3(h) => h.finalize().to_hex().to_string(),
+            Hasher::Crc32(h) => format!("{:08x}",h.finalize()),
+        }
+    }
+}
+
+/// 读一遍文件，按algorithms的顺序给出各个摘要的小写十六进制
+pub fn file_digests(filename : &str, algorithms : &[Algorithm]) -> io::Result<Vec<String>>{
+    let open = |flags| OpenOptions::new().read(true).custom_flags(flags).open(filename);
+    // 和 --magic 一样用O_NOATIME打开，计算摘要不应该改掉访问时间；不是属主时没有权限，退回到普通的打开方式
+    let mut file = match open(libc::O_NOATIME){
+        Err(e) if e.raw_os_error()==Some(libc::EPERM) => open(0)?,
+        res => res?,
+    };
+    let mut hashers : Vec<Hasher> = algorithms.iter().map(|&a| Hasher::new(a)).collect();
+    let mut buf = vec![0u8; CHUNK];
+    loop{
+        let n = match file.read(&mut buf){
+            Ok(n) => n,
+            Err(e) if e.kind()==io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if n==0{
+            break;
+        }
+        for hasher in hashers.iter_mut(){
+            hasher.update(&buf[..n]);
+        }
+    }
+    Ok(hashers.into_iter().map(Hasher::finish).collect())
+}
+
+/// 计算文件内容的一种摘要，以小写十六进制给出
+pub fn file_digest(filename : &str, algorithm : Algorithm) -> io::Result<String>{
+    Ok(file_digests(filename, &[algorithm])?.remove(0))
+}
+
+pub fn to_hex(bytes : &[u8]) -> String{
+    bytes.iter().map(|b| format!("{:02x}",b)).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// 各算法对空输入和"abc"的标准结果
+    const KNOWN : [(Algorithm, &str, &str); 5] = [
+        (Algorithm::Md5, "d41d8cd98f00b204e9800998ecf8427e", "900150983cd24fb0d6963f7d28e17f72"),
+        (Algorithm::Sha1, "da39a3ee5e6b4b0d3255bfef95601890afd80709", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+        (Algorithm::Sha256, "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+         "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        (Algorithm::Blake3, "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+         "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+        (Algorithm::Crc32, "00000000", "352441c2"),
+    ];
+
+    #[test]
+    fn known_answers(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-digest", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty");
+        let abc = dir.join("abc");
+        std::fs::write(&empty, b"").unwrap();
+        std::fs::write(&abc, b"abc").unwrap();
+
+        let empty_sums = file_digests(empty.to_str().unwrap(), &Algorithm::ALL).unwrap();
+        let abc_sums = file_digests(abc.to_str().unwrap(), &Algorithm::ALL).unwrap();
+        let single = file_digest(abc.to_str().unwrap(), Algorithm::Sha256).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for (i, (algorithm, empty, abc)) in KNOWN.iter().enumerate(){
+            assert_eq!(Algorithm::ALL[i], *algorithm);
+            assert_eq!(empty_sums[i], *empty, "{}", algorithm.name());
+            assert_eq!(abc_sums[i], *abc, "{}", algorithm.name());
+        }
+        assert_eq!(single, KNOWN[2].2);
+    }
+
+    #[test]
+    fn digest_keeps_atime(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-digest-atime", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        std::fs::write(&path, b"data").unwrap();
+        // 访问时间早于修改时间，relatime下普通的读取也会更新它
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        std::fs::File::options().write(true).open(&path).unwrap()
+            .set_times(std::fs::FileTimes::new().set_accessed(old)).unwrap();
+
+        file_digest(path.to_str().unwrap(), Algorithm::Md5).unwrap();
+        let atime = std::fs::metadata(&path).unwrap().accessed().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(atime, old);
+    }
+
+    #[test]
+    fn names_round_trip(){
+        for algorithm in Algorithm::ALL{
+            assert_eq!(Algorithm::from_name(algorithm.name()), Some(algorithm));
+        }
+        assert_eq!(Algorithm::from_name("sha512"), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! 用 FS_IOC_FIEMAP 得到文件在磁盘上的物理分布，用于查看碎片和共享（reflink）的空间
+use std::io;
+
+use crate::kstat::cpath;
+
+/// _IOWR('f', 11, struct fiemap)，struct fiemap 的大小就是头部的大小，extent数组是柔性数组成员
+const FS_IOC_FIEMAP : libc::Ioctl = libc::_IOWR::<FiemapHeader>('f' as u32, 11);
+/// 每次ioctl最多取回多少个extent
+const BATCH : usize = 256;
+
+const FIEMAP_EXTENT_LAST : u32 = 0x00000001;
+const FIEMAP_EXTENT_SHARED : u32 = 0x00002000;
+
+/// extent标志和名字
+const EXTENT_FLAGS : [(u32, &str); 11] = [
+    (0x00000001, "last"),
+    (0x00000002, "unknown"),
+    (0x00000004, "delalloc"),
+    (0x00000008, "encoded"),
+    (0x00000080, "encrypted"),
+    (0x00000100, "not_aligned"),
+    (0x00000200, "inline"),
+    (0x00000400, "tail"),
+    (0x00000800, "unwritten"),
+    (0x00001000, "merged"),
+    (0x00002000, "shared"),
+];
+
+/// struct fiemap 的头部，后面紧跟着fm_extent_count个fiemap_extent
+#[repr(C)]
+struct FiemapHeader{
+    fm_start : u64,
+    fm_length : u64,
+    fm_flags : u32,
+    fm_mapped_extents : u32,
+    fm_extent_count : u32,
+    fm_reserved : u32,
+}
+
+/// struct fiemap_extent
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct Extent{
+    pub logical : u64,
+    pub physical : u64,
+    pub length : u64,
+    reserved64 : [u64; 2],
+    pub flags : u32,
+    reserved : [u32; 3],
+}
+
+impl Extent{
+    /// 设置了的标志名，按位从低到高
+    pub fn flag_names(&self) -> Vec<&'static str>{
+        EXTENT_FLAGS.iter().filter(|&&(bit, _)| self.flags & bit!=0).map(|&(_, name)| name).collect()
+    }
+
+    pub fn shared(&self) -> bool{
+        self.flags & FIEMAP_EXTENT_SHARED!=0
+    }
+}
+
+/// 一次ioctl的缓冲区，保证头部和extent数组连续且对齐
+#[repr(C)]
+struct FiemapBuf{
+    header : FiemapHeader,
+    extents : [Extent; BATCH],
+}
+
+/// 取得文件的所有extent，文件系统不支持时返回的错误为EOPNOTSUPP或ENOTTY
+pub fn extents(filename : &str, follow : bool) -> io::Result<Vec<Extent>>{
+    let nofollow = if follow { 0 } else { libc::O_NOFOLLOW };
+    let fd = unsafe{ libc::open(cpath(filename).as_ptr(), libc::O_RDONLY|libc::O_CLOEXEC|nofollow) };
+    if fd==-1{
+        return Err(io::Error::last_os_error());
+    }
+    let res = read_extents(fd);
+    unsafe{
+        libc::close(fd);
+    }
+    res
+}
+
+fn read_extents(fd : i32) -> io::Result<Vec<Extent>>{
+    let mut res = Vec::new();
+    let mut buf = Box::new(FiemapBuf{
+        header : FiemapHeader{ fm_start : 0, fm_length : 0, fm_flags : 0, fm_mapped_extents : 0, fm_extent_count : 0, fm_reserved : 0 },
+        extents : [Extent::default(); BATCH],
+    });
+    let mut start = 0u64;
+    loop{
+        buf.header = FiemapHeader{
+            fm_start : start,
+            fm_length : u64::MAX - start,
+            // 不用FIEMAP_FLAG_SYNC：只是查看不应该触发写回，还没分配的部分会带上delalloc标志
+            fm_flags : 0,
+            fm_mapped_extents : 0,
+            fm_extent_count : BATCH as u32,
+            fm_reserved : 0,
+        };
+        if unsafe{ libc::ioctl(fd, FS_IOC_FIEMAP, &mut *buf as *mut FiemapBuf) }==-1{
+            return Err(io::Error::last_os_error());
+        }
+        let mapped = buf.header.fm_mapped_extents as usize;
+        if mapped==0{
+            break;
+        }
+        let batch = &buf.extents[..mapped];
+        res.extend_from_slice(batch);
+        let last = batch[mapped-1];
+        if last.flags & FIEMAP_EXTENT_LAST!=0{
+            break;
+        }
+        start = last.logical + last.length;
+    }
+    Ok(res)
+}
+
+/// 错误是否表示文件系统不支持FIEMAP
+pub fn unsupported(err : &io::Error) -> bool{
+    matches!(err.raw_os_error(), Some(libc::EOPNOTSUPP) | Some(libc::ENOTTY))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    #[cfg(all(target_env = "gnu", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn ioctl_number(){
+        assert_eq!(FS_IOC_FIEMAP, 0xC020660B);
+        assert_eq!(std::mem::size_of::<FiemapHeader>(), 32);
+        assert_eq!(std::mem::size_of::<Extent>(), 56);
+    }
+
+    #[test]
+    fn extent_flag_names(){
+        let extent = Extent{ flags : FIEMAP_EXTENT_LAST | FIEMAP_EXTENT_SHARED | 0x800, ..Extent::default() };
+        assert_eq!(extent.flag_names(), ["last", "unwritten", "shared"]);
+        assert!(extent.shared());
+        assert!(Extent::default().flag_names().is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! --human-readable / --si：把字节数和数量写成 1.5K、23M 这样的形式
+//! 和 ls -h、df -h 的写法一致：小于10时保留一位小数，都向上取整，所以不会把占满的空间显示得更小
+
+/// 数值的写法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeUnits{
+    Raw,        //原样输出
+    Binary,     //以1024为单位，K M G ...
+    Si,         //以1000为单位，k M G ...
+}
+
+impl SizeUnits{
+    /// 按当前的写法输出n
+    pub fn format(&self, n : u64) -> String{
+        match self{
+            SizeUnits::Raw => n.to_string(),
+            SizeUnits::Binary => human(n, 1024, &["K", "M", "G", "T", "P", "E"]),
+            SizeUnits::Si => human(n, 1000, &["k", "M", "G", "T", "P", "E"]),
+        }
+    }
+
+    /// 格式中的 :h 总是要换算，没有给出 --si 时按1024
+    pub fn or_binary(self) -> Self{
+        if self==SizeUnits::Raw { SizeUnits::Binary } else { self }
+    }
+}
+
+fn human(n : u64, base : u64, suffixes : &[&str]) -> String{
+    if n<base{
+        return n.to_string();
+    }
+    // 用整数算，避免大数转成f64时丢精度
+    let mut unit = base;
+    let mut index = 0;
+    while index+1<suffixes.len() && n/unit>=base{
+        unit *= base;
+        index += 1;
+    }
+    let whole = n/unit;
+    if whole<10{
+        // 一位小数，向上取整，进位到10时改用整数
+        let tenths = (n as u128*10).div_ceil(unit as u128);
+        if tenths<100{
+            return format!("{}.{}{}",tenths/10,tenths%10,suffixes[index]);
+        }
+    }
+    let value = n.div_ceil(unit);
+    // 向上取整后正好到了下一个单位，例如1023.5K写成1.0M
+    if value>=base && index+1<suffixes.len(){
+        return format!("1.0{}",suffixes[index+1]);
+    }
+    format!("{}{}",value,suffixes[index])
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn binary(n : u64) -> String{
+        SizeUnits::Binary.format(n)
+    }
+
+    fn si(n : u64) -> String{
+        SizeUnits::Si.format(n)
+    }
+
+    #[test]
+    fn binary_boundaries(){
+        assert_eq!(binary(0), "0");
+        assert_eq!(binary(999), "999");
+        assert_eq!(binary(1000), "1000");
+        assert_eq!(binary(1023), "1023");
+        assert_eq!(binary(1024), "1.0K");
+        assert_eq!(binary(1025), "1.1K");
+        assert_eq!(binary(1535), "1.5K");
+        assert_eq!(binary(1536), "1.5K");
+        assert_eq!(binary(1537), "1.6K");
+    }
+
+    #[test]
+    fn si_boundaries(){
+        assert_eq!(si(999), "999");
+        assert_eq!(si(1000), "1.0k");
+        assert_eq!(si(1023), "1.1k");
+        assert_eq!(si(1024), "1.1k");
+        assert_eq!(si(1535), "1.6k");
+    }
+
+    /// 向上取整跨过小数和整数、跨过单位的地方
+    #[test]
+    fn rounding_carries(){
+        assert_eq!(binary(10*1024-1), "10K");
+        assert_eq!(binary(10*1024), "10K");
+        assert_eq!(binary(10*1024+1), "11K");
+        assert_eq!(binary(1024*1024-1), "1.0M");
+        assert_eq!(binary(1024*1024), "1.0M");
+        assert_eq!(si(999_999), "1.0M");
+        assert_eq!(binary(u64::MAX), "16E");
+    }
+
+    #[test]
+    fn raw_and_or_binary(){
+        assert_eq!(SizeUnits::Raw.format(1535), "1535");
+        assert_eq!(SizeUnits::Raw.or_binary(), SizeUnits::Binary);
+        assert_eq!(SizeUnits::Si.or_binary(), SizeUnits::Si);
+    }
+}
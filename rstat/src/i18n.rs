@@ -0,0 +1,480 @@
+//! 默认输出和帮助中的文字，按 LC_ALL、LC_MESSAGES、LANG 的顺序选择语言
+//! 目前有英文和简体中文两种，找不到对应语言时用英文
+//! 只有给人看的默认输出使用这里的文字，-t、-c、--json 等给脚本用的输出不受语言影响
+use std::env;
+
+/// 一种语言的所有文字
+/// 标签不带冒号和对齐用的空格，由 head 和 label 统一加上
+#[derive(Debug)]
+pub struct Messages{
+    // 文件
+    pub file : &'static str,
+    pub size : &'static str,
+    pub blocks : &'static str,
+    pub io_block : &'static str,
+    pub alloc : &'static str,
+    pub sparse : &'static str,
+    pub content : &'static str,
+    pub device : &'static str,
+    pub inode : &'static str,
+    pub links : &'static str,
+    pub permission : &'static str,
+    pub uid : &'static str,
+    pub gid : &'static str,
+    pub flags : &'static str,
+    pub project : &'static str,
+    pub caps : &'static str,
+    pub acl : &'static str,
+    pub default_acl : &'static str,
+    pub access : &'static str,
+    pub modify : &'static str,
+    pub change : &'static str,
+    pub birth : &'static str,
+    pub holes : &'static str,
+    pub data : &'static str,
+    pub hole : &'static str,
+    pub extents : &'static str,
+    pub extent_columns : [&'static str; 4],
+    pub extents_summary : &'static str,     //两个{}：extent数和共享的字节数
+    pub extents_unsupported : &'static str,
+    pub xattrs : &'static str,
+    pub cannot_stat : &'static str,         //两个{}：文件名和错误
+    // --relative-times
+    pub ago : &'static str,                 //一个{}：例如 3 minutes
+    pub later : &'static str,               //将来的时间，一个{}
+    pub just_now : &'static str,
+    pub time_units : [(&'static str, &'static str); 6], //秒、分、时、天、月、年的单数和复数，一个{}：数量
+    pub file_types : [&'static str; 8],     //和FileType的顺序一致
+    // 文件系统
+    pub id : &'static str,
+    pub namelen : &'static str,
+    pub fs_type : &'static str,
+    pub block_size : &'static str,
+    pub fundamental_block_size : &'static str,
+    pub total : &'static str,
+    pub free : &'static str,
+    pub available : &'static str,
+    pub inodes : &'static str,
+    pub mount : &'static str,
+    pub super_block : &'static str,
+    // --usage
+    pub source : &'static str,
+    pub mounted_on : &'static str,
+    pub used : &'static str,
+    pub avail : &'static str,
+    pub use_percent : &'static str,
+    pub iused : &'static str,
+    pub ifree : &'static str,
+    pub iuse_percent : &'static str,
+    // -H
+    pub more_help : &'static str,
+}
+
+/// 行首标签的宽度，和 stat(1) 一样右对齐
+const HEAD_WIDTH : usize = 6;
+
+impl Messages{
+    /// 按环境变量选出语言
+    pub fn from_env() -> &'static Messages{
+        // 第一个非空的变量决定语言，和 setlocale 的规则一样
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        if locale.starts_with("zh"){
+            &ZH_CN
+        }
+        else{
+            &EN
+        }
+    }
+
+    /// 行首的标签，右对齐后加上冒号，例如 "  File: "
+    pub fn head(&self, label : &str) -> String{
+        let pad = HEAD_WIDTH.saturating_sub(display_width(label));
+        format!("{}{}: "," ".repeat(pad),label)
+    }
+
+    /// 和行首标签等宽的空白，用于多行内容的后续行
+    pub fn indent(&self, label : &str) -> String{
+        " ".repeat(display_width(&self.head(label)))
+    }
+
+    /// 行中间的标签，例如 "Blocks: "
+    pub fn label(&self, label : &str) -> String{
+        format!("{}: ",label)
+    }
+
+    /// 按显示宽度右对齐，用于表头
+    pub fn right(&self, text : &str, width : usize) -> String{
+        format!("{}{}"," ".repeat(width.saturating_sub(display_width(text))),text)
+    }
+
+    /// 把模板中的 {} 依次换成args
+    pub fn fill(&self, template : &str, args : &[&dyn std::fmt::Display]) -> String{
+        let mut res = String::new();
+        let mut args = args.iter();
+        let mut parts = template.split("{}");
+        res.push_str(parts.next().unwrap_or_default());
+        for part in parts{
+            if let Some(arg) = args.next(){
+                res.push_str(&arg.to_string());
+            }
+            res.push_str(part);
+        }
+        res
+    }
+}
+
+/// 终端上的显示宽度，中日韩文字和全角符号占两列
+pub fn display_width(text : &str) -> usize{
+    text.chars().map(|c| if is_wide(c) { 2 } else { 1 }).sum()
+}
+
+fn is_wide(c : char) -> bool{
+    matches!(c as u32, 0x1100..=0x115f | 0x2e80..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff | 0xfe30..=0xfe4f | 0xff00..=0xff60 | 0xffe0..=0xffe6)
+}
+
+pub const EN : Messages = Messages{
+    file : "File",
+    size : "Size",
+    blocks : "Blocks",
+    io_block : "IO Block",
+    alloc : "Alloc",
+    sparse : "Sparse",
+    content : "Content",
+    device : "Device",
+    inode : "Inode",
+    links : "Links",
+    permission : "Access",
+    uid : "Uid",
+    gid : "Gid",
+    flags : "Flags",
+    project : "Project",
+    caps : "Caps",
+    acl : "ACL",
+    default_acl : "DefACL",
+    access : "Access",
+    modify : "Modify",
+    change : "Change",
+    birth : "Birth",
+    holes : "Holes",
+    data : "data",
+    hole : "hole",
+    extents : "Extents",
+    extent_columns : ["logical", "physical", "length", "flags"],
+    extents_summary : "{} extents, {} bytes shared",
+    extents_unsupported : "not supported by the file system",
+    xattrs : "Xattrs",
+    cannot_stat : "stat: cannot stat '{}': {}",
+    ago : "{} ago",
+    later : "in {}",
+    just_now : "just now",
+    time_units : [("{} second", "{} seconds"), ("{} minute", "{} minutes"), ("{} hour", "{} hours"), ("{} day", "{} days"), ("{} month", "{} months"), ("{} year", "{} years")],
+    file_types : ["block device", "character device", "directory", "symbolic link", "regular file", "socket", "pipe", "unknow type"],
+    id : "ID",
+    namelen : "Namelen",
+    fs_type : "Type",
+    block_size : "Block size",
+    fundamental_block_size : "Fundamental block size",
+    total : "Total",
+    free : "Free",
+    available : "Available",
+    inodes : "Inodes",
+    mount : "Mount",
+    super_block : "Super",
+    source : "Source",
+    mounted_on : "Mounted on",
+    used : "Used",
+    avail : "Avail",
+    use_percent : "Use%",
+    iused : "IUsed",
+    ifree : "IFree",
+    iuse_percent : "IUse%",
+    more_help : "The helper for format mode ------ or you can just use 'rstat --help'
+The valid format sequences for files (without --file-system):
+
+    %a   access rights in octal (note '#' and '0' printf flags)
+    %A   access rights in human readable form
+    %b   number of blocks allocated (see %B)
+    %B   the size in bytes of each block reported by %b
+    %C   SELinux security context string
+    %d   device number in decimal
+    %D   device number in hex
+    %f   raw mode in hex
+    %F   file type
+    %g   group ID of owner
+    %G   group name of owner
+    %h   number of hard links
+    %i   inode number
+    %m   mount point
+    %n   file name
+    %N   quoted file name with dereference if symbolic link
+    %o   optimal I/O transfer size hint
+    %s   total size, in bytes
+    %t   major device type in hex, for character/block device special files
+    %T   minor device type in hex, for character/block device special files
+    %u   user ID of owner
+    %U   user name of owner
+    %w   time of file birth, human-readable; - if unknown
+    %W   time of file birth, seconds since Epoch; 0 if unknown
+    %x   time of last access, human-readable
+    %X   time of last access, seconds since Epoch
+    %y   time of last data modification, human-readable
+    %Y   time of last data modification, seconds since Epoch
+    %z   time of last status change, human-readable
+    %Z   time of last status change, seconds since Epoch
+    %{name}  file name, like %n
+    %{target}  target of a symbolic link; empty for other files
+    %{type}  file type, like %F; %{type:char} the ls(1) type letter, e.g. d
+    %{mode}  file type and access rights, e.g. -rw-r--r--; also :symbolic,
+             :octal (like %a) and :hex (like %f)
+    %{owner} %{group}  user and group name of owner; with ':id' the IDs
+    %{uid} %{gid} %{inode} %{links} %{size} %{blocks} %{ioblock}
+             like %u, %g, %i, %h, %s, %b and %o
+    %{dev}   device number in decimal; %{dev:hex} in hex
+    %{major} %{minor}  device type of character/block device special files
+    %{atime} %{mtime} %{ctime} %{btime}
+             time of last access, modification, status change and birth
+             with nanoseconds; ':epoch' seconds since Epoch, ':iso' ISO 8601
+             with time zone, ':full-iso', ':long-iso' or ':+FORMAT' as
+             with --time-style; ':ago' the age, e.g. 3 minutes ago or
+             in 2 hours for future times, and ':age' the age in seconds
+             (negative in the future), both relative to --now
+    %{xattr:NAME}  value of the extended attribute NAME; empty if not set
+    %{acl}   access ACL in short text form; empty if not set
+    %{acl:default}  default ACL of a directory in short text form
+    %{alloc} allocated size in bytes (%b * %B)
+    %{sparseness}  part of the size that is not allocated, from 0 to 1
+    %{caps}  file capabilities, e.g. cap_net_raw+ep; empty if not set
+    %{flags} inode flags in lsattr form, e.g. ----i---------e-------
+    %{flags:long}  inode flags as long names, e.g. Immutable, Extents
+    %{project}  project ID; empty if the file system has none
+    %{size:h}  size in human readable form, e.g. 1.5K (powers of 1000 with --si)
+    %{alloc:h} allocated size in human readable form
+    %{mime}  MIME type of the content of a regular file, e.g. image/png
+    %{magic} file(1) style description of the content of a regular file
+    %{md5} %{sha1} %{sha256} %{blake3} %{crc32}
+             digest of the content of a regular file; the file is only
+             read when one of these is used
+    %}   a literal '}', e.g. inside %{?COND:TEXT}
+
+  Conditional sections (files only):
+
+    %{?COND:TEXT}  output TEXT, which may contain directives, only if COND holds
+    %{if COND}...%{else}...%{end}  choose one part; %{else} is optional and
+             sections may be nested
+    COND is an expression as for --where below; in %{?COND:TEXT} COND ends at
+    the first ':' that completes it, so ':' in quotes or times is kept, e.g.
+    %{?mtime>2024-01-01T10:00:00:new}; an ls -l like line:
+    '%{mode} %{links} %{owner} %{group} %{if device}%{major}, %{minor}%{else}%{size}%{end} %n%{?link: -> %{target}}'
+
+  Expressions (conditional sections and --where):
+
+    file dir link block char fifo socket device setuid setgid sticky exec empty
+             true for files of that type or with that property
+    FIELD OP VALUE  with OP one of = == != < <= > >=, where FIELD is
+      type           file type as above or a find -type letter (= and != only)
+      name path      last part or all of the file name, matched against a
+                     pattern with * and ? (= and != only)
+      user group     name or ID of the owner (= and != only)
+      uid gid links inode  a number
+      size           bytes with an optional K, M, G, T or P suffix, e.g. 1.5M
+      mode perm      access rights in octal, e.g. 0644
+      atime mtime ctime  a duration with unit s, m, h, d, w or y before --now
+                     (-7d and 7d) or after it (+1h), or a time as for --now
+    A && B, A || B, !A and (A) combine them; quote values containing spaces or
+    ()&|!=<>, e.g. --where 'type=f && size>1M && mtime<-7d && user!=root'
+  
+  Valid format sequences for file systems:
+  
+    %a   free blocks available to non-superuser
+    %b   total data blocks in file system
+    %c   total file nodes in file system
+    %d   free file nodes in file system
+    %f   free blocks in file system
+    %i   file system ID in hex
+    %l   maximum length of filenames
+    %n   file name
+    %s   block size (for faster transfers)
+    %S   fundamental block size (for block counts)
+    %t   file system type in hex
+    %T   file system type in human readable form
+    %{blocks} %{bfree} %{bavail}  like %b, %f and %a; with ':h', e.g. %{bavail:h},
+             the size in bytes in human readable form
+    %{files} %{ffree}  like %c and %d; with ':h' in human readable form
+    %{flags} mount flags from statvfs, e.g. ro,nosuid,nodev,noexec,relatime
+    %{options}  mount and superblock options from /proc/self/mountinfo
+    %{options:mount}  %{options:super}  only the mount or the superblock options",
+};
+
+pub const ZH_CN : Messages = Messages{
+    file : "文件",
+    size : "大小",
+    blocks : "块数",
+    io_block : "IO 块",
+    alloc : "分配",
+    sparse : "稀疏",
+    content : "内容",
+    device : "设备",
+    inode : "Inode",
+    links : "硬链接",
+    permission : "权限",
+    uid : "属主",
+    gid : "属组",
+    flags : "标志",
+    project : "项目",
+    caps : "能力",
+    acl : "ACL",
+    default_acl : "默认ACL",
+    access : "最近访问",
+    modify : "最近更改",
+    change : "最近改动",
+    birth : "创建时间",
+    holes : "空洞",
+    data : "数据",
+    hole : "空洞",
+    extents : "区段",
+    extent_columns : ["逻辑偏移", "物理偏移", "长度", "标志"],
+    extents_summary : "共 {} 个区段，共享 {} 字节",
+    extents_unsupported : "文件系统不支持",
+    xattrs : "扩展属性",
+    cannot_stat : "stat: 无法获取'{}'的文件状态: {}",
+    ago : "{}前",
+    later : "{}后",
+    just_now : "刚刚",
+    time_units : [("{}秒", "{}秒"), ("{}分钟", "{}分钟"), ("{}小时", "{}小时"), ("{}天", "{}天"), ("{}个月", "{}个月"), ("{}年", "{}年")],
+    file_types : ["块设备", "字符设备", "目录", "符号链接", "普通文件", "套接字", "管道", "未知类型"],
+    id : "ID",
+    namelen : "文件名长度",
+    fs_type : "类型",
+    block_size : "块大小",
+    fundamental_block_size : "基本块大小",
+    total : "总计",
+    free : "空闲",
+    available : "可用",
+    inodes : "Inode",
+    mount : "挂载",
+    super_block : "超级块",
+    source : "来源",
+    mounted_on : "挂载点",
+    used : "已用",
+    avail : "可用",
+    use_percent : "已用%",
+    iused : "已用",
+    ifree : "可用",
+    iuse_percent : "已用%",
+    more_help : "格式模式的说明 ------ 也可以直接使用 'rstat --help'
+文件可用的格式序列（不带 --file-system 时）：
+
+    %a   八进制的访问权限（注意 printf 的 '#' 和 '0' 标志）
+    %A   人类可读的访问权限
+    %b   分配的块数（见 %B）
+    %B   %b 中每一块的字节数
+    %C   SELinux 安全上下文
+    %d   十进制的设备号
+    %D   十六进制的设备号
+    %f   十六进制的原始模式
+    %F   文件类型
+    %g   属组的 ID
+    %G   属组的名字
+    %h   硬链接数
+    %i   inode 号
+    %m   挂载点
+    %n   文件名
+    %N   带引号的文件名，符号链接时给出链接指向
+    %o   最佳 I/O 传输大小
+    %s   总大小，单位为字节
+    %t   十六进制的主设备号，用于字符设备和块设备
+    %T   十六进制的次设备号，用于字符设备和块设备
+    %u   属主的 ID
+    %U   属主的名字
+    %w   人类可读的创建时间；未知时为 -
+    %W   创建时间，自 Epoch 以来的秒数；未知时为 0
+    %x   人类可读的最近访问时间
+    %X   最近访问时间，自 Epoch 以来的秒数
+    %y   人类可读的最近数据修改时间
+    %Y   最近数据修改时间，自 Epoch 以来的秒数
+    %z   人类可读的最近状态改变时间
+    %Z   最近状态改变时间，自 Epoch 以来的秒数
+    %{name}  文件名，和 %n 相同
+    %{target}  符号链接指向的路径；其他文件为空
+    %{type}  文件类型，和 %F 相同；%{type:char} 为 ls(1) 的类型字母，例如 d
+    %{mode}  文件类型和访问权限，例如 -rw-r--r--；还可以用 :symbolic、
+             :octal（和 %a 相同）和 :hex（和 %f 相同）
+    %{owner} %{group}  属主和属组的名字；加上 ':id' 时为 ID
+    %{uid} %{gid} %{inode} %{links} %{size} %{blocks} %{ioblock}
+             和 %u、%g、%i、%h、%s、%b、%o 相同
+    %{dev}   十进制的设备号；%{dev:hex} 为十六进制
+    %{major} %{minor}  字符设备和块设备的设备号
+    %{atime} %{mtime} %{ctime} %{btime}
+             带纳秒的最近访问、修改、状态改变和创建时间；':epoch' 为自 Epoch
+             以来的秒数，':iso' 为带时区的 ISO 8601，':full-iso'、':long-iso'
+             和 ':+FORMAT' 和 --time-style 相同；':ago' 为距今多久，例如
+             3 minutes ago，将来的时间为 in 2 hours（总是英文），':age' 为距今的
+             秒数（将来的时间为负数），二者都相对于 --now
+    %{xattr:NAME}  扩展属性 NAME 的值；未设置时为空
+    %{acl}   简短文本形式的访问 ACL；未设置时为空
+    %{acl:default}  简短文本形式的目录默认 ACL
+    %{alloc} 分配的字节数（%b * %B）
+    %{sparseness}  大小中没有分配空间的部分，从 0 到 1
+    %{caps}  文件能力，例如 cap_net_raw+ep；未设置时为空
+    %{flags} lsattr 形式的 inode 标志，例如 ----i---------e-------
+    %{flags:long}  inode 标志的长名字，例如 Immutable, Extents
+    %{project}  项目 ID；文件系统不支持时为空
+    %{size:h}  人类可读的大小，例如 1.5K（带 --si 时以 1000 为单位）
+    %{alloc:h} 人类可读的分配大小
+    %{mime}  普通文件内容的 MIME 类型，例如 image/png
+    %{magic} file(1) 风格的普通文件内容说明
+    %{md5} %{sha1} %{sha256} %{blake3} %{crc32}
+             普通文件内容的摘要；只有用到这些时才会读取文件
+    %}   一个 '}'，例如用在 %{?条件:文字} 中
+
+  条件段（只用于文件）：
+
+    %{?条件:文字}  条件成立时才输出文字，文字中可以有指令
+    %{if 条件}...%{else}...%{end}  选出其中一部分输出；%{else} 可以省略，
+             条件段可以嵌套
+    条件是和下面 --where 相同的表达式；%{?条件:文字} 中的条件到第一个使它完整的
+    ':' 为止，引号和时间中的 ':' 会保留，例如 %{?mtime>2024-01-01T10:00:00:新}；
+    类似 ls -l 的一行：
+    '%{mode} %{links} %{owner} %{group} %{if device}%{major}, %{minor}%{else}%{size}%{end} %n%{?link: -> %{target}}'
+
+  表达式（条件段和 --where）：
+
+    file dir link block char fifo socket device setuid setgid sticky exec empty
+             文件是这种类型或者有这种属性时成立
+    字段 比较符 值  比较符为 = == != < <= > >=，字段为
+      type           上面的文件类型或者 find -type 的字母（只能用 = 和 !=）
+      name path      文件名的最后一部分或者整个文件名，和带 * 和 ? 的通配符
+                     比较（只能用 = 和 !=）
+      user group     属主、属组的名字或者 ID（只能用 = 和 !=）
+      uid gid links inode  数字
+      size           字节数，可以带 K、M、G、T、P 后缀，例如 1.5M
+      mode perm      八进制的访问权限，例如 0644
+      atime mtime ctime  带单位 s、m、h、d、w、y 的时长表示 --now 之前（-7d 和
+                     7d）或者之后（+1h），也可以按 --now 的写法给出时刻
+    用 A && B、A || B、!A 和 (A) 组合；值中有空白或者 ()&|!=<> 时要加引号，
+    例如 --where 'type=f && size>1M && mtime<-7d && user!=root'
+  
+  文件系统可用的格式序列：
+  
+    %a   普通用户可用的空闲块数
+    %b   文件系统的总数据块数
+    %c   文件系统的总 inode 数
+    %d   空闲的 inode 数
+    %f   文件系统的空闲块数
+    %i   十六进制的文件系统 ID
+    %l   文件名的最大长度
+    %n   文件名
+    %s   块大小（用于更快的传输）
+    %S   基本块大小（用于块计数）
+    %t   十六进制的文件系统类型
+    %T   人类可读的文件系统类型
+    %{blocks} %{bfree} %{bavail}  和 %b、%f、%a 相同；加上 ':h'（例如 %{bavail:h}）
+             时给出人类可读的字节数
+    %{files} %{ffree}  和 %c、%d 相同；加上 ':h' 时为人类可读的形式
+    %{flags} statvfs 给出的挂载标志，例如 ro,nosuid,nodev,noexec,relatime
+    %{options}  /proc/self/mountinfo 中的挂载选项和超级块选项
+    %{options:mount}  %{options:super}  只给出挂载选项或者超级块选项",
+};
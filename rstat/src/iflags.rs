@@ -0,0 +1,171 @@
+//! inode标志（lsattr/chattr看到的那些），例如immutable、append-only
+//!
+//! 按以下顺序获取，能拿到多少就报告多少：
+//! FS_IOC_GETFLAGS 得到ext2风格的标志；FS_IOC_FSGETXATTR 得到xfs风格的标志和项目ID；
+//! 两个ioctl都需要打开文件，对链接本身或者不支持的文件系统再退回到 statx 的 stx_attributes
+use crate::kstat::cpath;
+
+/// 编号按 _IOR('f', 1, long) 编码，内核实际读写的却是一个int，见 linux/fs.h
+const FS_IOC_GETFLAGS : libc::Ioctl = libc::FS_IOC_GETFLAGS;
+/// _IOR('X', 31, struct fsxattr)，各架构的方向位和大小位不同，交给libc按当前架构编码
+const FS_IOC_FSGETXATTR : libc::Ioctl = libc::_IOR::<FsXattr>('X' as u32, 31);
+
+/// struct fsxattr
+#[repr(C)]
+#[derive(Default)]
+struct FsXattr{
+    fsx_xflags : u32,
+    fsx_extsize : u32,
+    fsx_nextents : u32,
+    fsx_projid : u32,
+    fsx_cowextsize : u32,
+    fsx_pad : [u8; 8],
+}
+
+const FS_XFLAG_DAX : u32 = 0x00008000;
+
+/// ext2风格的标志：位、lsattr中的字母、lsattr -l 中的长名字，顺序和lsattr相同
+const FLAGS : [(u32, char, &str); 22] = [
+    (0x00000001, 's', "Secure_Deletion"),
+    (0x00000002, 'u', "Undelete"),
+    (0x00000008, 'S', "Synchronous_Updates"),
+    (0x00010000, 'D', "Synchronous_Directory_Updates"),
+    (0x00000010, 'i', "Immutable"),
+    (0x00000020, 'a', "Append_Only"),
+    (0x00000040, 'd', "No_Dump"),
+    (0x00000080, 'A', "No_Atime"),
+    (0x00000004, 'c', "Compression_Requested"),
+    (0x00000800, 'E', "Encrypted"),
+    (0x00004000, 'j', "Journaled_Data"),
+    (0x00001000, 'I', "Indexed_directory"),
+    (0x00008000, 't', "No_Tailmerging"),
+    (0x00020000, 'T', "Top_of_Directory_Hierarchies"),
+    (0x00080000, 'e', "Extents"),
+    (0x00800000, 'C', "No_COW"),
+    (0x02000000, 'x', "DAX"),
+    (0x40000000, 'F', "Casefold"),
+    (0x10000000, 'N', "Inline_Data"),
+    (0x20000000, 'P', "Project_Hierarchy"),
+    (0x00100000, 'V', "Verity"),
+    (0x00000400, 'm', "Dont_Compress"),
+];
+
+/// statx的属性位和对应的ext2风格标志
+const STATX_MAP : [(u64, u32); 7] = [
+    (libc::STATX_ATTR_COMPRESSED as u64, 0x00000004),
+    (libc::STATX_ATTR_IMMUTABLE as u64, 0x00000010),
+    (libc::STATX_ATTR_APPEND as u64, 0x00000020),
+    (libc::STATX_ATTR_NODUMP as u64, 0x00000040),
+    (libc::STATX_ATTR_ENCRYPTED as u64, 0x00000800),
+    (libc::STATX_ATTR_VERITY as u64, 0x00100000),
+    (libc::STATX_ATTR_DAX as u64, 0x02000000),
+];
+
+#[derive(Clone)]
+pub struct InodeFlags{
+    pub flags : u32,            //ext2风格的标志
+    pub complete : bool,        //flags是否来自FS_IOC_GETFLAGS；来自statx时只有部分标志是可靠的
+    pub projid : Option<u32>,   //项目ID，只有FS_IOC_FSGETXATTR能给出
+}
+
+impl InodeFlags{
+    /// 获取文件的inode标志，所有途径都失败时返回None
+    /// 符号链接本身没有标志，lsattr也不处理链接，直接返回None
+    pub fn read(filename : &str, follow : bool, mode : u32) -> Option<InodeFlags>{
+        let ftype = mode & libc::S_IFMT;
+        if ftype==libc::S_IFLNK{
+            return None;
+        }
+        let mut res = InodeFlags{ flags : 0, complete : false, projid : None };
+        let mut found = false;
+
+        // 只打开普通文件和目录，打开设备文件可能会有副作用
+        if ftype==libc::S_IFREG || ftype==libc::S_IFDIR{
+            let nofollow = if follow { 0 } else { libc::O_NOFOLLOW };
+            let fd = unsafe{
+                libc::open(cpath(filename).as_ptr(), libc::O_RDONLY|libc::O_NONBLOCK|libc::O_CLOEXEC|nofollow)
+            };
+            if fd!=-1{
+                let mut flags : libc::c_int = 0;
+                if unsafe{ libc::ioctl(fd, FS_IOC_GETFLAGS, &mut flags) }==0{
+                    res.flags = flags as u32;
+                    res.complete = true;
+                    found = true;
+                }
+                let mut fsx = FsXattr::default();
+                if unsafe{ libc::ioctl(fd, FS_IOC_FSGETXATTR, &mut fsx) }==0{
+                    if fsx.fsx_xflags & FS_XFLAG_DAX!=0{
+                        res.flags |= 0x02000000;
+                    }
+                    res.projid = Some(fsx.fsx_projid);
+                    found = true;
+                }
+                unsafe{
+                    libc::close(fd);
+                }
+            }
+        }
+
+        if !res.complete{
+            if let Some((attributes, mask)) = statx_attributes(filename, follow){
+                for (attr, flag) in STATX_MAP{
+                    if mask & attr!=0 && attributes & attr!=0{
+                        res.flags |= flag;
+                    }
+                }
+                found = true;
+            }
+        }
+        if found { Some(res) } else { None }
+    }
+
+    /// lsattr形式的字母，没有设置的位置为'-'
+    pub fn letters(&self) -> String{
+        FLAGS.iter().map(|&(bit, ch, _)| if self.flags & bit!=0 { ch } else { '-' }).collect()
+    }
+
+    /// lsattr -l 形式的长名字，没有任何标志时为"---"
+    pub fn names(&self) -> String{
+        let names : Vec<&str> = FLAGS.iter().filter(|&&(bit, _, _)| self.flags & bit!=0).map(|&(_, _, name)| name).collect();
+        if names.is_empty() { String::from("---") } else { names.join(", ") }
+    }
+}
+
+fn statx_attributes(filename : &str, follow : bool) -> Option<(u64, u64)>{
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+    let mut stx : libc::statx = unsafe{ std::mem::zeroed() };
+    let ret = unsafe{
+        libc::statx(libc::AT_FDCWD, cpath(filename).as_ptr(), flags, libc::STATX_BASIC_STATS, &mut stx)
+    };
+    if ret==-1{
+        return None;
+    }
+    Some((stx.stx_attributes, stx.stx_attributes_mask))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    /// 使用通用编码的64位架构上应当和头文件中的数值相同
+    #[test]
+    #[cfg(all(target_env = "gnu", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn ioctl_numbers(){
+        assert_eq!(FS_IOC_GETFLAGS, 0x80086601);
+        assert_eq!(FS_IOC_FSGETXATTR, 0x801c581f);
+        assert_eq!(std::mem::size_of::<FsXattr>(), 28);
+    }
+
+    #[test]
+    fn symlinks_have_no_flags(){
+        assert!(InodeFlags::read("/", false, libc::S_IFLNK | 0o777).is_none());
+    }
+
+    #[test]
+    fn letters_and_names(){
+        let flags = InodeFlags{ flags : 0x10 | 0x80000, complete : true, projid : None };
+        assert_eq!(flags.letters(), "----i---------e-------");
+        assert_eq!(flags.names(), "Immutable, Extents");
+        assert_eq!(InodeFlags{ flags : 0, complete : true, projid : None }.names(), "---");
+    }
+}
@@ -0,0 +1,73 @@
+//! --json 输出用到的最简单的JSON拼接，只需要对象和字符串转义，不值得为此引入别的库
+use std::fmt::Display;
+
+/// 按顺序拼接一个JSON对象
+pub struct JsonObject{
+    buf : String,
+}
+
+impl JsonObject{
+    pub fn new() -> Self{
+        JsonObject{ buf : String::from("{") }
+    }
+
+    fn key(&mut self, key : &str){
+        if self.buf.len()>1{
+            self.buf.push(',');
+        }
+        self.buf.push_str(&escape(key));
+        self.buf.push(':');
+    }
+
+    pub fn str(&mut self, key : &str, value : &str) -> &mut Self{
+        self.key(key);
+        self.buf.push_str(&escape(value));
+        self
+    }
+
+    /// 没有值时写null
+    pub fn opt_str(&mut self, key : &str, value : Option<&str>) -> &mut Self{
+        match value{
+            Some(value) => self.str(key, value),
+            None => self.raw(key, "null"),
+        }
+    }
+
+    pub fn num(&mut self, key : &str, value : impl Display) -> &mut Self{
+        self.key(key);
+        self.buf.push_str(&value.to_string());
+        self
+    }
+
+    /// 直接写入已经是JSON的值，例如嵌套的对象
+    pub fn raw(&mut self, key : &str, value : &str) -> &mut Self{
+        self.key(key);
+        self.buf.push_str(value);
+        self
+    }
+
+    pub fn finish(&mut self) -> String{
+        let mut res = std::mem::take(&mut self.buf);
+        res.push('}');
+        res
+    }
+}
+
+/// 带引号的JSON字符串
+pub fn escape(s : &str) -> String{
+    let mut res = String::with_capacity(s.len()+2);
+    res.push('"');
+    for ch in s.chars(){
+        match ch{
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32)<0x20 => res.push_str(&format!("\\u{:04x}",c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
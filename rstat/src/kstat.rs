@@ -1,23 +1,193 @@
-use std::{fmt::Display};
+use std::ffi::CString;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 use libc::lstat;
 pub use libc::{stat,statfs};
 use chrono::prelude::*;
 
+use crate::acl::{self, Acl};
+use crate::capability::{self, FileCaps};
+use crate::color::{ColorMode, Palette};
+use crate::compare;
+use crate::config::Config;
+use crate::digest::{self, Algorithm};
+use crate::human::SizeUnits;
+use crate::i18n::Messages;
+use crate::fiemap;
+use crate::iflags::InodeFlags;
+use crate::json::JsonObject;
+use crate::magic;
+use crate::manifest;
+use crate::mountinfo;
+use crate::mtree;
+use crate::pool;
+use crate::predicate::Predicate;
+use crate::sparse;
+use crate::style::{self, QuotingStyle, TimeStyle};
+use crate::watch;
+use crate::xattr;
+
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct FileStat{
-    filename: String,               //文件名
-    stat: stat,                     //文件信息
-    statfs:statfs,                  //文件系统信息
+    pub(crate) filename: String,               //文件名
+    pub(crate) stat: stat,                     //文件信息
+    pub(crate) statfs:statfs,                  //文件系统信息
 
-    blinker : bool,                 //该文件是否是一个链接
-    oriname : String,               //链接指向的源文件的名字
+    pub(crate) blinker : bool,                 //该文件是否是一个链接
+    pub(crate) oriname : String,               //链接指向的源文件的名字
 
-    alive : bool,                   //该信息是否属于一个成功解析的文件
-    err_info : String,              //错误信息
+    pub(crate) alive : bool,                   //该信息是否属于一个成功解析的文件
+    pub(crate) err_info : String,              //错误信息
+    pub(crate) follow : bool,                  //获取信息时是否跟随了链接，决定之后读取扩展属性等信息的方式
+    pub(crate) xattrs : Vec<(String, Vec<u8>)>, //获取信息时一并读好的扩展属性，ACL也在其中
+    pub(crate) flags : Option<InodeFlags>,     //获取信息时一并读好的inode标志
 
 //    gstat : libc::group
 }
+
+/// 两个FileStat之间不同的一个字段
+#[derive(Debug)]
+pub struct FieldDiff{
+    pub field : String,
+    pub old : String,
+    pub new : String,
+}
+
+/// 获取文件信息时一并读取的额外信息，每一项都需要额外的系统调用，只读取输出中用得到的
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extras{
+    pub xattrs : bool,      //扩展属性，ACL和文件能力也从中得到
+    pub flags : bool,       //inode标志和项目ID
+}
+
+impl Extras{
+    pub const ALL : Extras = Extras{ xattrs : true, flags : true };
+
+    /// 格式串中的指令用到的额外信息，条件段里的指令也算在内
+    pub fn of_format(format : &[FormatToken]) -> Extras{
+        let mut extras = Extras::default();
+        for token in format{
+            match token{
+                FormatToken::Directive('A') => extras.xattrs = true,
+                FormatToken::Named(name, _) if name=="acl" || name=="xattr" || name=="caps" => extras.xattrs = true,
+                FormatToken::Named(name, _) if name=="flags" || name=="project" => extras.flags = true,
+                FormatToken::Cond(_, then, otherwise) => {
+                    for inner in [Extras::of_format(then), Extras::of_format(otherwise)]{
+                        extras.xattrs |= inner.xattrs;
+                        extras.flags |= inner.flags;
+                    }
+                },
+                _ => {},
+            }
+        }
+        extras
+    }
+}
+
+impl FileStat{
+    /// fields()中会出现的所有字段名，xattrs代表所有"xattr:名字"形式的字段
+    pub const FIELDS : [&'static str; 15] = ["status","type","mode","owner","group","size","links",
+        "device","inode","atime","mtime","ctime","link","flags","xattrs"];
+
+    /// 按字段列出可以比较的值，字段名同时作为变化报告里的名字
+    /// 文件本身不存在时只有status一项
+    pub fn fields(&self) -> Vec<(String, String)>{
+        if !self.alive{
+            return vec![(String::from("status"), self.err_info.clone())];
+        }
+        let filestat = &self.stat;
+        let ftype = FileType::get_file_type(filestat.st_mode);
+        let fpermission = FilePermission::new(filestat.st_mode);
+        let fields = vec![
+            ("status", String::from("exists")),
+            ("type", ftype.to_string()),
+            ("mode", format!("{}/{}{}{}",fpermission.output_num(),ftype.output_short(),fpermission.output_char(),self.acl_mark())),
+            ("owner", format!("{}/{}",filestat.st_uid,get_username_with_id(filestat.st_uid))),
+            ("group", format!("{}/{}",filestat.st_gid,get_groupname_with_id(filestat.st_gid))),
+            ("size", filestat.st_size.to_string()),
+            ("links", filestat.st_nlink.to_string()),
+            ("device", format!("{:x}h/{}d",filestat.st_dev,filestat.st_dev)),
+            ("inode", filestat.st_ino.to_string()),
+            ("atime", get_time_full(filestat.st_atime, filestat.st_atime_nsec)),
+            ("mtime", get_time_full(filestat.st_mtime, filestat.st_mtime_nsec)),
+            ("ctime", get_time_full(filestat.st_ctime, filestat.st_ctime_nsec)),
+            ("link", self.oriname.clone()),
+            ("flags", self.inode_flags().map(|f| f.letters()).unwrap_or_else(|| String::from("-"))),
+        ];
+        let mut fields : Vec<(String, String)> = fields.into_iter().map(|(k,v)| (k.to_string(), v)).collect();
+        // 扩展属性的个数不固定，每个属性单独作为一个字段
+        // ACL本身也保存在扩展属性里，换成getfacl的写法便于阅读
+        for (name, value) in self.xattrs(){
+            let value = match Acl::parse(value){
+                Some(acl) if name==acl::ACCESS || name==acl::DEFAULT => acl.short_text(),
+                _ => xattr::quoted_value(value),
+            };
+            fields.push((format!("xattr:{}",name), value));
+        }
+        fields
+    }
+
+    /// 读取extras中要求的额外信息，保存下来供之后的输出和比较使用
+    /// 在获取信息的线程里调用，这样之后比较新旧两份信息时用的是各自获取时的值
+    pub fn load(mut self, extras : Extras) -> FileStat{
+        if !self.alive{
+            return self;
+        }
+        if extras.xattrs{
+            self.xattrs = xattr::list_with_values(&self.filename, self.follow).unwrap_or_default();
+        }
+        if extras.flags{
+            self.flags = InodeFlags::read(&self.filename, self.follow, self.stat.st_mode);
+        }
+        self
+    }
+
+    /// inode标志（lsattr），文件系统不支持或者没有读取时为None
+    pub fn inode_flags(&self) -> Option<&InodeFlags>{
+        self.flags.as_ref()
+    }
+
+    /// 带有扩展ACL时为"+"，附在符号形式的权限后面，和ls -l 相同
+    pub fn acl_mark(&self) -> &'static str{
+        if acl::has_extended(self.xattr(acl::ACCESS), self.xattr(acl::DEFAULT)) { "+" } else { "" }
+    }
+
+    /// 文件的扩展属性和它们的值
+    pub fn xattrs(&self) -> &[(String, Vec<u8>)]{
+        &self.xattrs
+    }
+
+    /// 已经读取的名为name的扩展属性的值
+    pub fn xattr(&self, name : &str) -> Option<&[u8]>{
+        self.xattrs.iter().find(|(n,_)| n==name).map(|(_,v)| v.as_slice())
+    }
+
+    /// 按字段名比较，返回所有不同的字段，只在一边出现的字段另一边的值记为"-"
+    /// 有一边不存在时只比较status，其余字段没有意义
+    pub fn diff(&self, other : &FileStat) -> Vec<FieldDiff>{
+        let count = if self.alive && other.alive { usize::MAX } else { 1 };
+        let old : Vec<(String, String)> = self.fields().into_iter().take(count).collect();
+        let new : Vec<(String, String)> = other.fields().into_iter().take(count).collect();
+        let lookup = |fields : &[(String, String)], name : &str| -> String{
+            fields.iter().find(|(k,_)| k==name).map(|(_,v)| v.clone()).unwrap_or_else(|| String::from("-"))
+        };
+
+        let mut diffs = Vec::new();
+        for (field, value) in &old{
+            let new_value = lookup(&new, field);
+            if *value!=new_value{
+                diffs.push(FieldDiff{field : field.clone(), old : value.clone(), new : new_value});
+            }
+        }
+        for (field, value) in new.iter().filter(|(k,_)| !old.iter().any(|(o,_)| o==k)){
+            diffs.push(FieldDiff{field : field.clone(), old : String::from("-"), new : value.clone()});
+        }
+        diffs
+    }
+}
 // //readlink
 // enum FileSystem{
 //     File(stat),
@@ -35,6 +205,39 @@ pub struct OptionSelected{
     files : Vec<String>,    //要查看的文件的名称
     output_inf : String,     //如果要规范化输出，其就是这个
     needflag : NeedFlag,    //用于判别输入时使用
+    needvalue : Option<String>, //正在等待参数值的长选项名，例如 --jobs
+    jobs : usize,           //获取文件信息时使用的线程数，1即为顺序获取
+    outfile : Option<String>, //-o 指定的输出文件，没有则输出到标准输出
+    bwatch : bool,          //是否持续监视文件的变化
+    interval : f64,         //监视模式下轮询的间隔，单位为秒
+    bcompare : bool,        //是否比较两个文件
+    ignore : Vec<String>,   //比较时忽略的字段
+    snapshot : Option<String>, //要生成的清单文件
+    verify : Option<String>,   //要校验的清单文件
+    digests : Vec<Algorithm>, //JSON、清单和mtree中要记录的文件内容摘要
+    bmtree : bool,          //是否输出mtree格式的规格说明
+    mtree_check : Option<String>, //用来检查目录树的mtree规格文件
+    bxattrs : bool,         //默认输出中是否列出扩展属性
+    bflags : bool,          //默认输出和JSON中是否列出inode标志
+    bmagic : bool,          //默认输出中是否识别普通文件的内容类型，需要读取文件
+    bjson : bool,           //是否以JSON输出
+    bholes : bool,          //是否列出普通文件中的数据段和空洞
+    bextents : bool,        //是否列出普通文件的物理extent
+    units : SizeUnits,      //默认输出中大小和数量的写法
+    busage : bool,          //文件系统是否按df的样子给出使用情况
+    ball_mounts : bool,     //是否列出所有挂载的文件系统
+    types : Vec<String>,    //列出所有挂载时只要这些类型
+    exclude_types : Vec<String>, //列出所有挂载时不要这些类型
+    messages : &'static Messages, //默认输出和帮助使用的语言
+    color : ColorMode,      //默认输出是否上色
+    time_style : TimeStyle, //默认输出中时间的写法
+    quoting : QuotingStyle, //默认输出中文件名的写法
+    config : Config,        //配置文件中的内容，预设在 --preset 选中时才检查
+    config_error : Option<StatError>, //读取配置文件时的错误，用到预设时才是错误，否则只给出警告
+    preset : Option<String>, //--preset 选中的预设名，命令行读完后再换成格式串
+    brelative : bool,       //默认输出中的时间是否写成距今多久
+    now : i64,              //计算距今多久时的"现在"，默认为启动时的时间，可以用 --now 固定下来
+    filter : Option<Predicate>, //--where 给出的条件，只输出满足条件的文件
 }
 /// 用于判断是否还需要规范化输入的状态
 #[derive(Debug,Eq, PartialEq)]
@@ -52,6 +255,41 @@ pub enum StatError{
     UnknowFile,             //未知的文件
     InvalidFormat,          //不能识别的规范输入
     MissingFormat,          //规范输入丢失
+    MissingValue,           //选项需要的参数丢失
+    InvalidValue,           //选项的参数不合法，例如 --jobs 0
+    MissingFile,            //没有给出要查看的文件
+    InvalidManifest,        //清单文件的格式不对
+    InvalidConfig(String),  //配置文件的格式不对，给出文件、行号和原因
+    UnknownPreset(String),  //配置文件中没有 --preset 给出的预设
+    InvalidExpression(String), //条件表达式写错了，给出位置和原因
+    Io(io::Error),          //写输出时出错，例如 -o 指定的文件无法创建
+}
+
+/// 给人看的错误信息，main中在前面加上 "rstat: " 输出到标准错误
+impl Display for StatError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            StatError::InvalidOption => write!(f,"unrecognized option"),
+            StatError::WrongOption => write!(f,"the given options cannot be used together"),
+            StatError::UnknowFile => write!(f,"unknown file"),
+            StatError::InvalidFormat => write!(f,"invalid format string"),
+            StatError::MissingFormat => write!(f,"missing format string"),
+            StatError::MissingValue => write!(f,"option requires an argument"),
+            StatError::InvalidValue => write!(f,"invalid argument for option"),
+            StatError::MissingFile => write!(f,"missing file operand"),
+            StatError::InvalidManifest => write!(f,"invalid manifest"),
+            StatError::InvalidConfig(reason) => write!(f,"invalid configuration: {}",reason),
+            StatError::UnknownPreset(name) => write!(f,"unknown preset '{}'",name),
+            StatError::InvalidExpression(reason) => write!(f,"invalid expression: {}",reason),
+            StatError::Io(e) => write!(f,"{}",e),
+        }
+    }
+}
+
+impl From<io::Error> for StatError{
+    fn from(e : io::Error) -> Self{
+        StatError::Io(e)
+    }
 }
 
 impl OptionSelected{
@@ -66,10 +304,52 @@ impl OptionSelected{
             files: vec![],
             needflag : NeedFlag::Default,
             output_inf : String::new(),
+            needvalue : None,
+            jobs : 1,
+            outfile : None,
+            bwatch : false,
+            interval : 1.0,
+            bcompare : false,
+            ignore : vec![],
+            snapshot : None,
+            verify : None,
+            digests : vec![],
+            bmtree : false,
+            mtree_check : None,
+            bxattrs : false,
+            bflags : false,
+            bmagic : false,
+            bjson : false,
+            bholes : false,
+            bextents : false,
+            units : SizeUnits::Raw,
+            busage : false,
+            ball_mounts : false,
+            types : vec![],
+            exclude_types : vec![],
+            messages : Messages::from_env(),
+            color : ColorMode::Never,
+            time_style : TimeStyle::FullIso,
+            quoting : QuotingStyle::Literal,
+            config : Config::default(),
+            config_error : None,
+            preset : None,
+            brelative : false,
+            now : Utc::now().timestamp(),
+            filter : None,
         }
     }
     /// 解析命令行输入的内容   并从string容器中读取选项信息到当前选项结构体
     pub fn readoption(&mut self , args : Vec<String>) -> Result<(),StatError>{
+        // 先用配置文件中的默认值，命令行上的选项再覆盖它们
+        // 配置文件有错时不影响不用它的运行，整个文件都不用，等到 --preset 时再报错
+        match Config::load(){
+            Ok(config) => self.config = config,
+            Err(e) => self.config_error = Some(e),
+        }
+        self.color = self.config.color.unwrap_or(self.color);
+        self.time_style = self.config.time_style.clone().unwrap_or(TimeStyle::FullIso);
+        self.quoting = self.config.quoting.unwrap_or(self.quoting);
         //let mut opt : OptionSelected=OptionSelected::default();
         let mlen = args.len();
         // 遍历传来的string，获取信息，默认最前面一个数组丢弃（直接从env拿来的第一个无用）
@@ -81,7 +361,7 @@ impl OptionSelected{
             };
             let length=iter.len();
 
-            if length<=0 {
+            if length==0 {
                 continue;
             }
             // 判定现在的输入模式即拥有的选项等
@@ -89,11 +369,13 @@ impl OptionSelected{
                 self.output_inf.push_str(iter);
                 self.needflag=NeedFlag::Over;
             }
-            else if iter.starts_with("--"){
-                if length<=2{
+            else if let Some(name) = self.needvalue.take(){
+                self.readvalue(&name, iter)?;
+            }
+            else if let Some(longoption) = iter.strip_prefix("--"){
+                if longoption.is_empty(){
                     continue;
                 }
-                let longoption=&iter[2..];
                 match longoption{
                     "dereference" => self.blink=true,
                     "format=FORMAT" => {
@@ -105,11 +387,64 @@ impl OptionSelected{
                     },
                     "more-help" => self.bmhelper=true,
                     "file-system" => self.bfilesystem=true,
-                    _ => return Err(StatError::InvalidOption),
+                    "jobs" => self.needvalue=Some(String::from("jobs")),
+                    "output" => self.needvalue=Some(String::from("output")),
+                    "watch" => self.bwatch=true,
+                    "interval" => self.needvalue=Some(String::from("interval")),
+                    "compare" => self.bcompare=true,
+                    "ignore" => self.needvalue=Some(String::from("ignore")),
+                    "snapshot" => self.needvalue=Some(String::from("snapshot")),
+                    "verify" => self.needvalue=Some(String::from("verify")),
+                    "digest" => if self.digests.is_empty(){
+                        self.digests.push(Algorithm::Sha256);
+                    },
+                    "mtree" => self.bmtree=true,
+                    "xattrs" => self.bxattrs=true,
+                    "flags" => self.bflags=true,
+                    "magic" => self.bmagic=true,
+                    "json" => self.bjson=true,
+                    "holes" => self.bholes=true,
+                    "extents" => self.bextents=true,
+                    "human-readable" => self.units=SizeUnits::Binary,
+                    "si" => self.units=SizeUnits::Si,
+                    "usage" => self.busage=true,
+                    "relative-times" => self.brelative=true,
+                    "now" => self.needvalue=Some(String::from("now")),
+                    "where" => self.needvalue=Some(String::from("where")),
+                    "time-style" => self.needvalue=Some(String::from("time-style")),
+                    "quoting-style" => self.needvalue=Some(String::from("quoting-style")),
+                    "preset" => self.needvalue=Some(String::from("preset")),
+                    // 和ls一样，不带参数的 --color 就是 always
+                    "color" => self.color=ColorMode::Always,
+                    "all-mounts" => self.ball_mounts=true,
+                    "type" => self.needvalue=Some(String::from("type")),
+                    "exclude-type" => self.needvalue=Some(String::from("exclude-type")),
+                    "mtree-check" => self.needvalue=Some(String::from("mtree-check")),
+                    // 形如 --jobs=4 的写法，等号后面即为参数值
+                    _ => match longoption.split_once('='){
+                        Some((name,value)) => self.readvalue(name, value)?,
+                        None => return Err(StatError::InvalidOption),
+                    },
                 };
             }
             else if iter.starts_with('-'){
-                for ch in iter.chars(){
+                for (i,ch) in iter.char_indices(){
+                    // 需要参数的短选项和getopt一样把同一个参数里剩下的部分当作值，例如 -j4、-o/tmp/out
+                    let name = match ch{
+                        'j' => "jobs",
+                        'o' => "output",
+                        _ => "",
+                    };
+                    if !name.is_empty(){
+                        let rest = &iter[i+ch.len_utf8()..];
+                        if rest.is_empty(){
+                            self.needvalue=Some(String::from(name));
+                        }
+                        else{
+                            self.readvalue(name, rest)?;
+                        }
+                        break;
+                    }
                     match ch{
                         'L' => self.blink=true,
                         'c' => {
@@ -121,192 +456,360 @@ impl OptionSelected{
                         },
                         'H' => self.bmhelper=true,
                         'f' => self.bfilesystem=true,
+                        'h' => self.units=SizeUnits::Binary,
                         '-' => {},
                         _ => return Err(StatError::InvalidOption),
                     };
                 }
             }
             else{
-                // 这里一定要注意,在Rust下String末尾是不带'\0'的,但是c的数组末尾以这个结束,那么我们没这个的话就会解析不出来
-                self.files.push(format!("{}\0",iter));
+                // 文件名原样保存，调用c接口时再通过cpath转换成以'\0'结尾的字符串
+                self.files.push(iter.to_string());
+            }
+        }
+        // 选项需要参数，但是命令行已经结束了
+        if self.needvalue.is_some(){
+            return Err(StatError::MissingValue);
+        }
+        // 预设就是一个有名字的 -c，不管写在 -c 前面还是后面都不能一起用
+        if let Some(name) = self.preset.clone(){
+            if self.bfilter{
+                return Err(StatError::WrongOption);
+            }
+            if let Some(e) = self.config_error.take(){
+                return Err(e);
+            }
+            self.output_inf = self.config.preset(&name)?;
+            self.bfilter = true;
+        }
+        // 配置文件有错但是没有用到其中的预设，忽略整个文件，只给出警告
+        if let Some(e) = &self.config_error{
+            eprintln!("rstat: warning: {}, ignoring it",e);
+        }
+        // 校验清单时可以不给文件，要查看的文件都记录在清单里
+        // 按mtree检查时不给文件就检查当前目录
+        if self.files.is_empty() && self.mtree_check.is_some(){
+            self.files.push(String::from("."));
+        }
+        // 所有挂载点由mountinfo给出，不能再另外指定文件
+        if self.ball_mounts{
+            if !self.files.is_empty(){
+                return Err(StatError::WrongOption);
             }
+            self.files = mountinfo::mount_points(&self.types, &self.exclude_types)?;
+            return Ok(());
         }
+        if self.files.is_empty() && self.verify.is_none(){
+            return Err(StatError::MissingFile);
+        }
+        Ok(())
+    }
+
+    /// 读取带参数的选项的值
+    fn readvalue(&mut self, name : &str, value : &str) -> Result<(),StatError>{
+        match name{
+            "jobs" => {
+                self.jobs = match value.parse::<usize>(){
+                    Ok(n) if n>0 => n,
+                    _ => return Err(StatError::InvalidValue),
+                };
+            },
+            "output" => self.outfile=Some(value.to_string()),
+            "snapshot" => self.snapshot=Some(value.to_string()),
+            "verify" => self.verify=Some(value.to_string()),
+            "mtree-check" => self.mtree_check=Some(value.to_string()),
+            "interval" => {
+                self.interval = match value.parse::<f64>(){
+                    Ok(n) if n>0.0 && n.is_finite() => n,
+                    _ => return Err(StatError::InvalidValue),
+                };
+            },
+            "digest" => {
+                self.digests.clear();
+                for name in value.split(',').filter(|n| !n.is_empty()){
+                    let algorithm = Algorithm::from_name(name).ok_or(StatError::InvalidValue)?;
+                    if !self.digests.contains(&algorithm){
+                        self.digests.push(algorithm);
+                    }
+                }
+            },
+            "color" => self.color = ColorMode::from_name(value).ok_or(StatError::InvalidValue)?,
+            "time-style" => self.time_style = TimeStyle::from_name(value).ok_or(StatError::InvalidValue)?,
+            "now" => self.now = style::parse_timestamp(value).ok_or(StatError::InvalidValue)?,
+            "where" => self.filter = Some(Predicate::parse(value)?),
+            "quoting-style" => self.quoting = QuotingStyle::from_name(value).ok_or(StatError::InvalidValue)?,
+            // 命令行读完后才知道有没有 -c，那时再取出预设的格式串
+            "preset" => {
+                if self.preset.is_some(){
+                    return Err(StatError::WrongOption);
+                }
+                self.preset = Some(value.to_string());
+            },
+            "type" | "exclude-type" => {
+                let list = if name=="type" { &mut self.types } else { &mut self.exclude_types };
+                list.extend(value.split(',').filter(|t| !t.is_empty()).map(String::from));
+            },
+            "ignore" => {
+                for field in value.split(',').filter(|f| !f.is_empty()){
+                    if !FileStat::FIELDS.contains(&field){
+                        return Err(StatError::InvalidValue);
+                    }
+                    self.ignore.push(field.to_string());
+                }
+            },
+            _ => return Err(StatError::InvalidOption),
+        };
         Ok(())
     }
 
     /// 根据现在的选项结构体来获取相应的输出方式及信息获取
-    pub fn output(&self) -> Result<(),StatError>{
-        let mut filestats:Vec<FileStat> = Vec::new();
+    /// 返回值为程序的退出码，例如 --compare 时两个文件不同则为1
+    pub fn output(&self) -> Result<i32,StatError>{
 
         // 如果同时需要简化输出和格式化输出,我们选择直接返回错误,即不允许这种错误
         if self.bterse&&self.bfilter{
             return Err(StatError::WrongOption);
         }
+        // JSON本身就是一种输出格式，不能再和-t、-c一起用
+        if self.bjson&&(self.bterse||self.bfilter){
+            return Err(StatError::WrongOption);
+        }
+        // 列出所有挂载只针对文件系统，类型过滤只在列出所有挂载时有意义
+        if (self.ball_mounts&&!self.bfilesystem) || (!self.ball_mounts&&(!self.types.is_empty()||!self.exclude_types.is_empty())){
+            return Err(StatError::WrongOption);
+        }
+        // 使用情况只针对文件系统，并且替代的是默认输出
+        if self.busage&&(!self.bfilesystem||self.bterse||self.bfilter){
+            return Err(StatError::WrongOption);
+        }
+        // 监视模式只针对文件本身，不支持文件系统
+        if self.bwatch&&self.bfilesystem{
+            return Err(StatError::WrongOption);
+        }
+        // 条件针对的是文件，只用于逐个输出文件的默认输出、-c、-t 和 --json
+        if self.filter.is_some()&&(self.bfilesystem||self.bwatch||self.bcompare||self.bmtree
+            ||self.snapshot.is_some()||self.verify.is_some()||self.mtree_check.is_some()){
+            return Err(StatError::WrongOption);
+        }
+        // 比较模式需要且只需要两个文件
+        if self.bcompare&&(self.bfilesystem||self.bwatch||self.files.len()!=2){
+            return Err(StatError::WrongOption);
+        }
 
         // 获取本地时间偏移量,即所处时区
         let dt = Local::now().offset().to_string();
         //println!("dt: {}", dt.offset());
 
-        
-        // 遍历所有文件,并按照选项拿出我们需要的信息
-        for iter in self.files.iter(){     
-            if self.bfilesystem{
-                filestats.push(get_file_statfs(iter.clone()));
-            }
-            else if self.blink{
-                filestats.push(get_file_stat_link(iter.clone()));
+        // 格式串只在这里解析一次，之后每个文件直接按解析结果输出
+        let format = parse_format(&self.output_inf)?;
+        // 条件针对的是文件的类型和权限，文件系统上没有意义
+        if self.bfilesystem && format.iter().any(|t| matches!(t, FormatToken::Cond(..))){
+            return Err(StatError::InvalidFormat);
+        }
+
+        // 输出目标：-o 指定的文件或者标准输出，都加上缓冲
+        let stdout = io::stdout();
+        let mut out : Box<dyn Write> = match &self.outfile{
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(stdout.lock())),
+        };
+
+        if self.bwatch{
+            let interval = std::time::Duration::from_secs_f64(self.interval);
+            watch::watch(&mut out, &self.files, self.getter(Extras::ALL), !self.blink, interval)?;
+            return Ok(0);
+        }
+
+        if let Some(manifest) = &self.snapshot{
+            let mut file = BufWriter::new(File::create(manifest)?);
+            manifest::snapshot(&mut file, &self.files, &self.digests)?;
+            file.flush()?;
+            return Ok(0);
+        }
+
+        if let Some(manifest) = &self.verify{
+            let clean = manifest::verify(&mut out, manifest)?;
+            out.flush()?;
+            return Ok(if clean { 0 } else { 1 });
+        }
+
+        if self.bmtree{
+            for root in &self.files{
+                mtree::emit(&mut out, root, &self.digests)?;
             }
-            else{
-                filestats.push(get_file_stat(iter.clone()));
+            out.flush()?;
+            return Ok(0);
+        }
+
+        if let Some(spec) = &self.mtree_check{
+            let mut clean = true;
+            for root in &self.files{
+                clean &= mtree::check(&mut out, spec, root)?;
             }
+            out.flush()?;
+            return Ok(if clean { 0 } else { mtree::MISMATCH_EXIT });
+        }
+
+        if self.bcompare{
+            let getter = self.getter(Extras::ALL);
+            let a = getter(self.files[0].clone());
+            let b = getter(self.files[1].clone());
+            let same = compare::compare(&mut out, &a, &b, &self.ignore)?;
+            out.flush()?;
+            return Ok(if same { 0 } else { 1 });
+        }
+
+        self.render(&mut out, self.stat_iter(&format), &format, &dt)?;
+
+        if self.bmhelper{
+            output_more_help(&mut out, self.messages)?;
+        }
+        out.flush()?;
+        Ok(0)
+    }
+
+    /// 需要上色时给出LS_COLORS，写到 -o 指定的文件时不算终端
+    fn palette(&self) -> Option<Palette>{
+        let to_terminal = self.outfile.is_none() && unsafe{ libc::isatty(libc::STDOUT_FILENO) }==1;
+        if self.color.enabled(to_terminal) { Some(Palette::from_env()) } else { None }
+    }
+
+    /// 按照选项选出获取信息的方式，extras中的额外信息也在这里一并读取
+    pub fn getter(&self, extras : Extras) -> impl Fn(String) -> FileStat + Copy + Send + Sync + 'static{
+        let stat : fn(String) -> FileStat = if self.bfilesystem{
+            get_file_statfs
+        }
+        else if self.blink{
+            get_file_stat_link
+        }
+        else{
+            get_file_stat
+        };
+        // 文件系统没有这些信息
+        let extras = if self.bfilesystem { Extras::default() } else { extras };
+        move |name| stat(name).load(extras)
+    }
+
+    /// 按选项输出文件时要读取的额外信息，format为 -c 给出的格式
+    /// 默认输出和JSON总是带ACL标记和文件能力，inode标志要打开文件，只在 --flags 时读取
+    pub fn extras(&self, format : &[FormatToken]) -> Extras{
+        if self.bterse{
+            Extras::default()
+        }
+        else if self.bfilter{
+            Extras::of_format(format)
+        }
+        else{
+            Extras{ xattrs : true, flags : self.bflags }
         }
+    }
+
+    /// 按选项逐个获取文件信息，不会一次性把所有结果都放到内存里
+    /// 多线程时结果仍按输入顺序给出；有 --where 时去掉不满足条件的文件，获取失败的仍然给出
+    pub fn stat_iter(&self, format : &[FormatToken]) -> Box<dyn Iterator<Item = FileStat> + '_>{
+        let getter = self.getter(self.extras(format));
+        let filestats : Box<dyn Iterator<Item = FileStat> + '_> = if self.jobs>1{
+            Box::new(pool::OrderedIter::new(self.files.clone(), self.jobs, getter))
+        }
+        else{
+            Box::new(self.files.iter().cloned().map(getter))
+        };
+        match &self.filter{
+            Some(filter) => Box::new(filestats.filter(move |f| !f.alive || filter.matches(f, self.now))),
+            None => filestats,
+        }
+    }
 
+    /// 把文件信息按照选项渲染到任意的输出目标上，标准输出、文件或者内存中的缓冲区都可以
+    pub fn render<W, I>(&self, out : &mut W, filestats : I, format : &[FormatToken], offset : &str) -> io::Result<()>
+    where
+        W: Write,
+        I: Iterator<Item = FileStat>,
+    {
         //根据选项中的值来选择合适的输出方式
         //由于我们限制了不能同时出现-c和-t，故而这两者之间可以理解为直接不可能同时出现
-        if self.bterse{
+        if self.bjson{
             if self.bfilesystem{
-                OptionSelected::output_with_terse_filesystem(filestats);
+                self.output_json(out, filestats, OptionSelected::json_filesystem)
             }
             else{
-                OptionSelected::output_with_terse_file(filestats);
+                self.output_json(out, filestats, OptionSelected::json_file)
             }
         }
-        else if self.bfilter{
+        else if self.bterse{
             if self.bfilesystem{
-                OptionSelected::output_with_fileter_filesystem(&self.output_inf,&filestats);
+                OptionSelected::output_with_terse_filesystem(out, filestats)
             }
             else{
-                OptionSelected::output_with_fileter_file(&self.output_inf,&filestats);
+                OptionSelected::output_with_terse_file(out, filestats)
             }
         }
-        else {
+        else if self.bfilter{
             if self.bfilesystem{
-                OptionSelected::output_normal_filesystem(filestats);
+                self.output_with_fileter_filesystem(out, format, filestats)
             }
             else{
-                OptionSelected::output_normal_file(filestats,&dt);
+                self.output_with_fileter_file(out, format, filestats)
             }
         }
-
-        if self.bmhelper{
-            output_more_help();
+        else if self.bfilesystem && self.busage{
+            self.output_usage_filesystem(out, filestats)
+        }
+        else if self.bfilesystem{
+            self.output_normal_filesystem(out, filestats)
+        }
+        else{
+            self.output_normal_file(out, filestats, offset)
         }
-        Ok(())
     }
 
     //其实作为这些输出代码来说，这些功能其实完全可以写到一个代码里，但为了方便就算了，反正ctrl c/v 的事
     //这里就把每一种的文件系统和另外一个分开了，本来来说完全不必
-    #[inline]
-    pub fn output_with_fileter_file(command : &str , filestat : &Vec<FileStat>){
-    //    println!("{}:",command);
-    //  按理来讲这里其实应该把解析分开，然后将其拆分后在进行输出，但是这次由于不是着重于这个功能，就每次都解析一次吧
-        for iter in filestat{
-            let mut bflag : bool = false;
-            for ch in command.chars(){
-                if ch=='%'{
-                    if bflag{
-                        print!("%");
-                    }
-                    bflag = true;
-                }
-                else{
-                    let filestat = &iter.stat;
-                    if bflag{
-                        match ch{
-                            'a' => {print!("{}",FilePermission::new(filestat.st_mode).output_num());},
-                            'A' => {print!("{}",FilePermission::new(filestat.st_mode).output_char());},
-                            'b' => {print!("{}",filestat.st_blocks);},
-                            'B' => {print!("{}",filestat.st_blksize/filestat.st_blocks);},
-                            'C' => {print!("rstat: failed to get security context of 'dfs.sh': No data available \n?",);},//这里因为没找到对应的接口，先暂缓
-                            'd' => {print!("{}",filestat.st_dev);},
-                            'D' => {print!("{:x}",filestat.st_dev);},
-                            'f' => {print!("{:x}",filestat.st_mode)},
-                            'F' => {print!("{}",FileType::get_file_type(filestat.st_mode));},
-                            'g' => {print!("{}",filestat.st_gid);},
-                            'G' => {print!("{}",get_groupname_with_id(filestat.st_gid));},
-                            'h' => {print!("{}",filestat.st_nlink);},
-                            'i' => {print!("{}",filestat.st_ino)},
-                            'm' => {print!("/");},//这里的功能没有实际完成，思路是读取/proc/mounts进行比对，有的话就输出相应的，否则就是'/'
-                            'n' => {print!("{}",iter.filename)},
-                            'N' => {
-                                if iter.blinker{
-                                    print!("'{}' -> '{}'",iter.filename,iter.oriname);
-                                }
-                                else{
-                                    print!("'{}'",iter.filename);
-                                }
-                            },
-                            'o' => {print!("{}",filestat.st_blksize);},
-                            's' => {print!("{}",filestat.st_size)},
-                            't' => {print!("!t!");},//未完成
-                            'T' => {print!("!T!");},
-                            'u' => {print!("{}",filestat.st_uid);},
-                            'U' => {print!("{}",get_username_with_id(filestat.st_uid));},
-                            'w' => {print!("-");},
-                            'W' => {print!("-");},
-                            'x' => {print!("{}",get_time_utc2local(filestat.st_atime, filestat.st_atime_nsec));},
-                            'X' => {print!("{}",filestat.st_atime_nsec);},
-                            'y' => {print!("{}",get_time_utc2local(filestat.st_atime, filestat.st_mtime_nsec));},
-                            'Y' => {print!("{}",filestat.st_mtime);},
-                            'z' => {print!("{}",get_time_utc2local(filestat.st_atime, filestat.st_ctime_nsec));},
-                            'Z' => {print!("{}",filestat.st_ctime);},
-                            _   => {print!("{}",ch);},
-                        };
-                    }
-                    else{
-                        print!("{}",ch);
-                    }
-                    bflag = false;
-                }
+    pub fn output_with_fileter_file<W : Write>(&self, out : &mut W, format : &[FormatToken], filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        for iter in filestats{
+            self.write_file_tokens(out, format, &iter)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// 按格式输出一个文件，条件段按条件选出一部分再递归输出
+    fn write_file_tokens<W : Write>(&self, out : &mut W, format : &[FormatToken], iter : &FileStat) -> io::Result<()>{
+        for token in format{
+            match token{
+                FormatToken::Text(text) => out.write_all(text.as_bytes())?,
+                FormatToken::Directive(ch) => write_file_directive(out, *ch, iter)?,
+                FormatToken::Named(name, arg) => write_file_named(out, name, arg.as_deref(), iter, self.units, self.now)?,
+                FormatToken::Cond(predicate, then, otherwise) => {
+                    let branch = if predicate.matches(iter, self.now) { then } else { otherwise };
+                    self.write_file_tokens(out, branch, iter)?;
+                },
             }
-        }print!("\n");
+        }
+        Ok(())
     }
 
-    #[inline]
-    pub fn output_with_fileter_filesystem(command : &str , filestat : &Vec<FileStat>){
-        println!("{}:",command);
-        for iter in filestat{
-            let mut bflag :bool = false;
-            for ch in command.chars(){
-                if ch=='%'{
-                    if bflag{
-                        print!("%");
-                    }
-                    bflag = true;
-                }
-                else{
-                    let filestat = &iter.statfs;
-                    if bflag{
-                        match ch{
-                            'a' => {print!("{}",filestat.f_bavail);},
-                            'b' => {print!("{}",filestat.f_blocks);},
-                            'c' => {print!("{}",filestat.f_files);},
-                            'd' => {print!("{}",filestat.f_ffree);},
-                            'f' => {print!("{}",filestat.f_bfree)},
-                            'i' => {print!("{:x}",431254)},//老问题，暂时还没找到提取出来的方法
-                            'n' => {print!("{}",iter.filename)},
-                            's' => {print!("{}",filestat.f_bsize)},//这里可能搞反了
-                            'S' => {print!("{}",filestat.f_frsize)},
-                            't' => {print!("{:x}",filestat.f_type);},
-                            'T' => {print!("{}",filestat.f_type);},
-                            _   => {print!("{}",ch);},
-                        };
-                    }
-                    else{
-                        print!("{}",ch);
-                    }
-                    bflag = false;
+    pub fn output_with_fileter_filesystem<W : Write>(&self, out : &mut W, format : &[FormatToken], filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        for iter in filestats{
+            for token in format{
+                match token{
+                    FormatToken::Text(text) => out.write_all(text.as_bytes())?,
+                    FormatToken::Directive(ch) => write_filesystem_directive(out, *ch, &iter)?,
+                    FormatToken::Named(name, arg) => write_filesystem_named(out, name, arg.as_deref(), &iter, self.units)?,
+                    // 文件系统没有文件类型和权限，output()中已经拒绝了条件段
+                    FormatToken::Cond(..) => {},
                 }
             }
-        }print!("\n");
+            writeln!(out)?;
+        }
+        Ok(())
     }
 
-    #[inline]
-    pub fn output_with_terse_file(filestat: Vec<FileStat>){
-        for iter in filestat{
+    pub fn output_with_terse_file<W : Write>(out : &mut W, filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        for iter in filestats{
             if iter.alive{
                 let filestat =&iter.stat;
-                println!("{} {} {} {:x} {} {} {:x} {} {} {} {} {} {} {} {} {}",
+                writeln!(out, "{} {} {} {:x} {} {} {:x} {} {} {} {} {} {} {} {} {}",
                     iter.filename,
                     filestat.st_size,filestat.st_blocks,
                     filestat.st_mode,filestat.st_uid,filestat.st_gid,
@@ -314,39 +817,40 @@ impl OptionSelected{
                     filestat.st_ino,filestat.st_nlink,0,0,
                     filestat.st_atime,filestat.st_mtime,filestat.st_ctime,
                     0,filestat.st_blksize,
-                );
+                )?;
             }
             else{
-                println!("stat: cannot stat '{}': {}",iter.filename,iter.err_info);
+                writeln!(out, "stat: cannot stat '{}': {}",iter.filename,iter.err_info)?;
             }
         }
+        Ok(())
     }
 
-    #[inline]
-    pub fn output_with_terse_filesystem(filestat: Vec<FileStat>){
-        for iter in filestat{
+    pub fn output_with_terse_filesystem<W : Write>(out : &mut W, filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        for iter in filestats{
             if iter.alive{
                 let filestatfs =&iter.statfs;
-                println!("{}  {:x} {} {} {} {} {} {} {}",
+                writeln!(out, "{}  {:x} {} {} {} {} {} {} {}",
                     iter.filename,
                     11111111,   //该字段本该是filestatfs.f_fsid,但是目前使用的该libc库并没有给予该结构体任何display的方法，甚至内容都是pri的，难搞
                     filestatfs.f_namelen,filestatfs.f_bavail,
                     filestatfs.f_bsize,filestatfs.f_frsize,filestatfs.f_ffree,
                     filestatfs.f_bfree,filestatfs.f_type
-                );
+                )?;
             }
             else{
-                println!("stat: cannot stat '{}': {}",iter.filename,iter.err_info);
+                writeln!(out, "stat: cannot stat '{}': {}",iter.filename,iter.err_info)?;
             }
         }
+        Ok(())
     }
 
-    #[inline]
-    pub fn output_normal_file(filestat: Vec<FileStat> , offset : &str){
-    //    println!("{}:","normal  file");
-        for iter in filestat{
+    pub fn output_normal_file<W : Write>(&self, out : &mut W, filestats : impl Iterator<Item = FileStat>, offset : &str) -> io::Result<()>{
+        let msg = self.messages;
+        let palette = self.palette();
+        for iter in filestats{
             if !iter.alive{
-                println!("stat: cannot stat '{}': {}",iter.filename,iter.err_info);
+                writeln!(out, "{}",msg.fill(msg.cannot_stat, &[&iter.filename, &iter.err_info]))?;
                 continue;
             }
             let filestat =&iter.stat;
@@ -354,68 +858,787 @@ impl OptionSelected{
             let fpermission = FilePermission::new(filestat.st_mode);
 
             if iter.blinker{
-                println!("  File: {} -> {}",iter.filename,iter.oriname);
+                // 链接指向的文件按它自己的类型上色，不存在时指向的名字也按or上色
+                let target = std::fs::metadata(&iter.filename).ok();
+                let (name, oriname) = (self.quoting.quote(&iter.filename), self.quoting.quote(&iter.oriname));
+                let (name, oriname) = match &palette{
+                    Some(p) => (
+                        p.paint(p.for_file(&iter.filename, filestat.st_mode, target.is_none()), &name),
+                        p.paint(match &target{
+                            Some(t) => p.for_file(&iter.oriname, std::os::unix::fs::MetadataExt::mode(t), false),
+                            None => p.for_file(&iter.oriname, filestat.st_mode, true),
+                        }, &oriname),
+                    ),
+                    None => (name, oriname),
+                };
+                writeln!(out, "{}{} -> {}",msg.head(msg.file),name,oriname)?;
             }
             else{
-                println!("  File: {}",iter.filename);
-            }
-            println!("  Size: {:<16}Blocks: {:<11}IO Block: {:<7}{}"
-                ,filestat.st_size,filestat.st_blocks,filestat.st_blksize,
-                ftype);
-            println!("Device: {:<16}Inode: {:<12}Links: {}",
-                format!("{:x}h/{}d",filestat.st_dev,filestat.st_dev),
-                filestat.st_ino,filestat.st_nlink);
-            println!("Access: ({}/{}{})  Uid: ({:>5}/{:>8})   Gid: ({:>5}/{:>8})",
-                fpermission.output_num(),ftype.output_short(),fpermission.output_char(),
-                filestat.st_uid,get_username_with_id(filestat.st_uid),
-                filestat.st_gid,get_groupname_with_id(filestat.st_gid)
-            );
-
-            println!("Access: {}.{} {}\nModify: {}.{} {}\nChange: {}.{} {}\nBirth: -",
-                    get_time_utc2local(filestat.st_atime, filestat.st_atime_nsec),filestat.st_atime_nsec,offset,
-                    get_time_utc2local(filestat.st_mtime, filestat.st_mtime_nsec),filestat.st_mtime_nsec,offset,
-                    get_time_utc2local(filestat.st_ctime, filestat.st_ctime_nsec),filestat.st_ctime_nsec,offset,
-                );
-
-        }print!("\n");
-    }
-
-    #[inline]
-    pub fn output_normal_filesystem(filestat: Vec<FileStat>){
-    //    println!("{}:{}","normal  filesys",offset);
-        for iter in filestat{
+                let name = self.quoting.quote(&iter.filename);
+                let name = match &palette{
+                    Some(p) => p.paint(p.for_file(&iter.filename, filestat.st_mode, false), &name),
+                    None => name,
+                };
+                writeln!(out, "{}{}",msg.head(msg.file),name)?;
+            }
+            // 换算时Blocks给出的是占用的字节数，块数本身换算没有意义
+            let blocks = match self.units{
+                SizeUnits::Raw => filestat.st_blocks.to_string(),
+                units => units.format(sparse::allocated(filestat) as u64),
+            };
+            writeln!(out, "{}{:<16}{}{:<11}{}{:<7}{}",
+                msg.head(msg.size),self.units.format(filestat.st_size as u64),
+                msg.label(msg.blocks),blocks,
+                msg.label(msg.io_block),filestat.st_blksize,
+                ftype.localized(msg))?;
+            // 只有普通文件才有稀疏的说法
+            if ftype==FileType::Regular{
+                writeln!(out, "{}{:<16}{}{:.1}%",
+                    msg.head(msg.alloc),self.units.format(sparse::allocated(filestat) as u64),
+                    msg.label(msg.sparse),sparse::sparseness(filestat)*100.0)?;
+                // 识别内容要读文件，只在 --magic 时进行
+                if self.bmagic{
+                    match magic::sniff(&iter.filename, iter.follow){
+                        Ok(content) => writeln!(out, "{}{} ({})",msg.head(msg.content),content.description,content.mime)?,
+                        Err(e) => writeln!(out, "{}{}",msg.head(msg.content),e)?,
+                    }
+                }
+            }
+            writeln!(out, "{}{:<16}{}{:<12}{}{}",
+                msg.head(msg.device),format!("{:x}h/{}d",filestat.st_dev,filestat.st_dev),
+                msg.label(msg.inode),filestat.st_ino,
+                msg.label(msg.links),filestat.st_nlink)?;
+            // 先对齐再上色，转义序列不能算进宽度里
+            let mut mode = format!("{}{}",ftype.output_short(),fpermission.output_char());
+            let mut user = format!("{:>8}",get_username_with_id(filestat.st_uid));
+            let mut group = format!("{:>8}",get_groupname_with_id(filestat.st_gid));
+            if let Some(p) = &palette{
+                mode = p.mode(&mode, filestat.st_mode);
+                if lookup_username(filestat.st_uid).is_none(){
+                    user = p.dim(&user);
+                }
+                if lookup_groupname(filestat.st_gid).is_none(){
+                    group = p.dim(&group);
+                }
+            }
+            writeln!(out, "{}({}/{}{})  {}({:>5}/{})   {}({:>5}/{})",
+                msg.head(msg.permission),fpermission.output_num(),mode,iter.acl_mark(),
+                msg.label(msg.uid),filestat.st_uid,user,
+                msg.label(msg.gid),filestat.st_gid,group
+            )?;
+            if let Some(flags) = iter.inode_flags(){
+                match flags.projid{
+                    Some(projid) => writeln!(out, "{}{} ({})  {}{}",msg.head(msg.flags),flags.letters(),flags.names(),msg.label(msg.project),projid)?,
+                    None => writeln!(out, "{}{} ({})",msg.head(msg.flags),flags.letters(),flags.names())?,
+                }
+            }
+            if let Some(caps) = iter.xattr(capability::XATTR_NAME).and_then(FileCaps::parse){
+                writeln!(out, "{}{}",msg.head(msg.caps),caps.to_text())?;
+            }
+            // 带有扩展ACL时按getfacl的样子列出所有条目
+            for (label, name) in [(msg.acl, acl::ACCESS), (msg.default_acl, acl::DEFAULT)]{
+                let Some(acl) = iter.xattr(name).and_then(Acl::parse) else{
+                    continue;
+                };
+                if name==acl::ACCESS && !acl.is_extended(){
+                    continue;
+                }
+                for (i, line) in acl.lines().iter().enumerate(){
+                    writeln!(out, "{}{}",if i==0 { msg.head(label) } else { msg.indent(label) },line)?;
+                }
+            }
+
+            for (label, sec, nsec) in [
+                (msg.access, filestat.st_atime, filestat.st_atime_nsec),
+                (msg.modify, filestat.st_mtime, filestat.st_mtime_nsec),
+                (msg.change, filestat.st_ctime, filestat.st_ctime_nsec),
+            ]{
+                if self.brelative{
+                    writeln!(out, "{}{}",msg.head(label),style::relative_time(sec, self.now, msg))?;
+                }
+                else{
+                    writeln!(out, "{}{}",msg.head(label),self.time_style.format(sec, nsec, offset))?;
+                }
+            }
+            writeln!(out, "{}-",msg.head(msg.birth))?;
+
+            if self.bholes && ftype==FileType::Regular{
+                match sparse::segments(&iter.filename, iter.follow, filestat.st_size){
+                    Ok(segments) if segments.is_empty() => writeln!(out, "{}-",msg.head(msg.holes))?,
+                    Ok(segments) => {
+                        for (i, segment) in segments.iter().enumerate(){
+                            writeln!(out, "{}{} {}..{} ({})",if i==0 { msg.head(msg.holes) } else { msg.indent(msg.holes) },
+                                if segment.data { msg.data } else { msg.hole },
+                                segment.start,segment.end,segment.end-segment.start)?;
+                        }
+                    },
+                    Err(e) => writeln!(out, "{}{}",msg.head(msg.holes),e)?,
+                }
+            }
+            if self.bextents && ftype==FileType::Regular{
+                match fiemap::extents(&iter.filename, iter.follow){
+                    Ok(extents) => {
+                        let [logical, physical, length, flags] = msg.extent_columns;
+                        writeln!(out, "{}{} {} {}  {}",msg.head(msg.extents),
+                            msg.right(logical, 12),msg.right(physical, 14),msg.right(length, 12),flags)?;
+                        let indent = msg.indent(msg.extents);
+                        for extent in &extents{
+                            writeln!(out, "{}{:>12} {:>14} {:>12}  {}",indent,extent.logical,extent.physical,extent.length,
+                                extent.flag_names().join(","))?;
+                        }
+                        let shared : u64 = extents.iter().filter(|e| e.shared()).map(|e| e.length).sum();
+                        writeln!(out, "{}{}",indent,msg.fill(msg.extents_summary, &[&extents.len(), &shared]))?;
+                    },
+                    Err(e) if fiemap::unsupported(&e) => writeln!(out, "{}{}",msg.head(msg.extents),msg.extents_unsupported)?,
+                    Err(e) => writeln!(out, "{}{}",msg.head(msg.extents),e)?,
+                }
+            }
+            if self.bxattrs{
+                let xattrs = iter.xattrs();
+                if xattrs.is_empty(){
+                    writeln!(out, "{}-",msg.head(msg.xattrs))?;
+                }
+                else{
+                    writeln!(out, "{}",msg.head(msg.xattrs).trim_end())?;
+                    for (name, value) in xattrs{
+                        writeln!(out, "  {}={}",name,xattr::quoted_value(value))?;
+                    }
+                }
+            }
+        }
+        writeln!(out)
+    }
+
+    /// 输出一个JSON数组，每个文件一个对象，一边获取一边输出
+    pub fn output_json<W, F>(&self, out : &mut W, filestats : impl Iterator<Item = FileStat>, object : F) -> io::Result<()>
+    where
+        W: Write,
+        F: Fn(&Self, &FileStat) -> String,
+    {
+        write!(out, "[")?;
+        for (i, iter) in filestats.enumerate(){
+            if i>0{
+                write!(out, ",")?;
+            }
+            let text = if iter.alive{
+                object(self, &iter)
+            }
+            else{
+                JsonObject::new().str("name", &iter.filename).str("error", &iter.err_info).finish()
+            };
+            write!(out, "\n  {}",text)?;
+        }
+        writeln!(out, "\n]")
+    }
+
+    fn json_file(&self, iter : &FileStat) -> String{
+        let filestat = &iter.stat;
+        let ftype = FileType::get_file_type(filestat.st_mode);
+        let fpermission = FilePermission::new(filestat.st_mode);
+        let caps = iter.xattr(capability::XATTR_NAME).and_then(FileCaps::parse).map(|c| c.to_text());
+        let mut object = JsonObject::new();
+        object.str("name", &iter.filename)
+            .str("type", &ftype.to_string())
+            .str("mode", &fpermission.output_num())
+            .str("mode_text", &format!("{}{}{}",ftype.output_short(),fpermission.output_char(),iter.acl_mark()))
+            .num("uid", filestat.st_uid)
+            .str("user", &get_username_with_id(filestat.st_uid))
+            .num("gid", filestat.st_gid)
+            .str("group", &get_groupname_with_id(filestat.st_gid))
+            .num("size", filestat.st_size)
+            .num("blocks", filestat.st_blocks)
+            .num("io_block", filestat.st_blksize)
+            .num("device", filestat.st_dev)
+            .num("inode", filestat.st_ino)
+            .num("links", filestat.st_nlink)
+            .num("atime", format!("{}.{:09}",filestat.st_atime,filestat.st_atime_nsec))
+            .num("mtime", format!("{}.{:09}",filestat.st_mtime,filestat.st_mtime_nsec))
+            .num("ctime", format!("{}.{:09}",filestat.st_ctime,filestat.st_ctime_nsec))
+            .opt_str("link_target", if iter.blinker { Some(iter.oriname.as_str()) } else { None })
+            .opt_str("capabilities", caps.as_deref());
+        if ftype==FileType::Regular{
+            object.num("allocated", sparse::allocated(filestat))
+                .num("sparseness", format!("{:.4}",sparse::sparseness(filestat)));
+        }
+        if !self.digests.is_empty() && ftype==FileType::Regular{
+            let mut digests = JsonObject::new();
+            if let Ok(sums) = digest::file_digests(&iter.filename, &self.digests){
+                for (algorithm, sum) in self.digests.iter().zip(sums){
+                    digests.str(algorithm.name(), &sum);
+                }
+            }
+            object.raw("digests", &digests.finish());
+        }
+        if self.bholes && ftype==FileType::Regular{
+            let segments = match sparse::segments(&iter.filename, iter.follow, filestat.st_size){
+                Ok(segments) => segments.iter()
+                    .map(|s| JsonObject::new()
+                        .str("type", if s.data { "data" } else { "hole" })
+                        .num("offset", s.start)
+                        .num("length", s.end-s.start)
+                        .finish())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                Err(_) => String::new(),
+            };
+            object.raw("segments", &format!("[{}]",segments));
+        }
+        if self.bextents && ftype==FileType::Regular{
+            // 不支持FIEMAP时三项都是null，和空文件的空列表区分开
+            match fiemap::extents(&iter.filename, iter.follow){
+                Ok(extents) => {
+                    let list = extents.iter()
+                        .map(|e| JsonObject::new()
+                            .num("logical", e.logical)
+                            .num("physical", e.physical)
+                            .num("length", e.length)
+                            .raw("flags", &format!("[{}]",e.flag_names().iter()
+                                .map(|f| format!("\"{}\"",f)).collect::<Vec<_>>().join(",")))
+                            .finish())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let shared : u64 = extents.iter().filter(|e| e.shared()).map(|e| e.length).sum();
+                    object.raw("extents", &format!("[{}]",list))
+                        .num("extent_count", extents.len())
+                        .num("shared_bytes", shared);
+                },
+                Err(_) => {
+                    object.raw("extents", "null")
+                        .raw("extent_count", "null")
+                        .raw("shared_bytes", "null");
+                },
+            }
+        }
+        if self.bflags{
+            match iter.inode_flags(){
+                Some(flags) => {
+                    object.str("inode_flags", &flags.letters())
+                        .str("inode_flag_names", &flags.names());
+                    match flags.projid{
+                        Some(projid) => object.num("project_id", projid),
+                        None => object.raw("project_id", "null"),
+                    };
+                },
+                None => {
+                    object.raw("inode_flags", "null")
+                        .raw("inode_flag_names", "null")
+                        .raw("project_id", "null");
+                },
+            }
+        }
+        if self.bxattrs{
+            let mut xattrs = JsonObject::new();
+            for (name, value) in iter.xattrs(){
+                xattrs.str(name, &xattr::display_value(value));
+            }
+            object.raw("xattrs", &xattrs.finish());
+        }
+        object.finish()
+    }
+
+    fn json_filesystem(&self, iter : &FileStat) -> String{
+        let filestat = &iter.statfs;
+        let mut object = JsonObject::new();
+        object.str("name", &iter.filename)
+            .str("type", &format!("{:x}",filestat.f_type))
+            .num("namelen", filestat.f_namelen)
+            .num("block_size", filestat.f_bsize)
+            .num("fundamental_block_size", filestat.f_frsize)
+            .num("blocks", filestat.f_blocks)
+            .num("blocks_free", filestat.f_bfree)
+            .num("blocks_available", filestat.f_bavail)
+            .num("inodes", filestat.f_files)
+            .num("inodes_free", filestat.f_ffree)
+            .opt_str("mount_flags", mountinfo::mount_flags(&iter.filename).ok().as_deref());
+        let mount = mountinfo::find(&iter.filename);
+        object.opt_str("mount_options", mount.as_ref().map(|m| m.mount_options.as_str()))
+            .opt_str("super_options", mount.as_ref().map(|m| m.super_options.as_str()));
+        if self.busage{
+            let usage = Usage::new(filestat);
+            object.opt_str("source", mount.as_ref().map(|m| m.source.as_str()))
+                .opt_str("mount_point", mount.as_ref().map(|m| m.mount_point.as_str()))
+                .opt_str("fs_type", mount.as_ref().map(|m| m.fs_type.as_str()))
+                .num("size_bytes", usage.size)
+                .num("used_bytes", usage.used)
+                .num("available_bytes", usage.avail)
+                .raw("use_percent", &usage.use_percent().map_or(String::from("null"), |p| p.to_string()))
+                .num("inodes_used", usage.iused)
+                .raw("inode_use_percent", &usage.inode_use_percent().map_or(String::from("null"), |p| p.to_string()));
+        }
+        object.finish()
+    }
+
+    /// 和df一样给出文件系统的使用情况，以及所在的挂载点
+    pub fn output_usage_filesystem<W : Write>(&self, out : &mut W, filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        let msg = self.messages;
+        let percent = |p : Option<u64>| p.map_or(String::from("-"), |p| format!("{}%",p));
+        for iter in filestats{
             if !iter.alive{
-                println!("stat: cannot stat '{}': {}",iter.filename,iter.err_info);
+                writeln!(out, "{}",msg.fill(msg.cannot_stat, &[&iter.filename, &iter.err_info]))?;
+                continue;
+            }
+            let usage = Usage::new(&iter.statfs);
+            writeln!(out, "{}\"{}\"",msg.head(msg.file),iter.filename)?;
+            let mount = mountinfo::find(&iter.filename);
+            writeln!(out, "{}{:<15} {}{:<15} {}{}",
+                msg.head(msg.source),mount.as_ref().map_or("-", |m| m.source.as_str()),
+                msg.label(msg.mounted_on),mount.as_ref().map_or("-", |m| m.mount_point.as_str()),
+                msg.label(msg.fs_type),mount.as_ref().map_or("-", |m| m.fs_type.as_str()))?;
+            writeln!(out, "{}{:<16}{}{:<16}{}{:<16}{}{}",
+                msg.head(msg.size),self.units.format(usage.size),
+                msg.label(msg.used),self.units.format(usage.used),
+                msg.label(msg.avail),self.units.format(usage.avail),
+                msg.label(msg.use_percent),percent(usage.use_percent()))?;
+            writeln!(out, "{}{:<16}{}{:<15}{}{:<16}{}{}",
+                msg.head(msg.inodes),self.units.format(usage.files),
+                msg.label(msg.iused),self.units.format(usage.iused),
+                msg.label(msg.ifree),self.units.format(usage.ffree),
+                msg.label(msg.iuse_percent),percent(usage.inode_use_percent()))?;
+        }
+        writeln!(out)
+    }
+
+    pub fn output_normal_filesystem<W : Write>(&self, out : &mut W, filestats : impl Iterator<Item = FileStat>) -> io::Result<()>{
+        let msg = self.messages;
+        for iter in filestats{
+            if !iter.alive{
+                writeln!(out, "{}",msg.fill(msg.cannot_stat, &[&iter.filename, &iter.err_info]))?;
                 continue;
             }
             let filestat =&iter.statfs;
 
-            println!("  File: \"{}\"",iter.filename);
-            println!("    ID: {:x} Namelen: {:<8}Type: {}/{}",
-                    0xfedc9aa3bd65bc57 as i128,filestat.f_namelen,"ext2","ext3");
+            writeln!(out, "{}\"{}\"",msg.head(msg.file),iter.filename)?;
+            writeln!(out, "{}{:x} {}{:<8}{}ext2/ext3",
+                    msg.head(msg.id),0xfedc9aa3bd65bc57_u64,msg.label(msg.namelen),filestat.f_namelen,msg.label(msg.fs_type))?;
             //statfs中数据就随便写了，这里的数据还要翻文档去解析，太麻烦了就不做了，主要是不是很重要的东西,这里我就先按我这边的输出了
-            println!("Block size: {:<11}Fundamental block size: {}",filestat.f_frsize,filestat.f_bsize);
-            println!("Blocks: Total: {:<11}Free: {:<11}Available: {}",
-                    filestat.f_blocks,filestat.f_bfree,filestat.f_bavail);
-            println!("Inodes: Total: {:<11}Free: {}",filestat.f_files,filestat.f_ffree);
+            writeln!(out, "{}{:<11}{}{}",msg.head(msg.block_size),filestat.f_frsize,msg.label(msg.fundamental_block_size),filestat.f_bsize)?;
+            // 换算时块数给出对应的字节数，inode数直接换算
+            let blocks = |n : u64| match self.units{
+                SizeUnits::Raw => n.to_string(),
+                units => units.format(n*filestat.f_frsize as u64),
+            };
+            writeln!(out, "{}{}{:<11}{}{:<11}{}{}",
+                    msg.head(msg.blocks),msg.label(msg.total),blocks(filestat.f_blocks),
+                    msg.label(msg.free),blocks(filestat.f_bfree),
+                    msg.label(msg.available),blocks(filestat.f_bavail))?;
+            writeln!(out, "{}{}{:<11}{}{}",
+                    msg.head(msg.inodes),msg.label(msg.total),self.units.format(filestat.f_files),
+                    msg.label(msg.free),self.units.format(filestat.f_ffree))?;
+            match mountinfo::mount_flags(&iter.filename){
+                Ok(flags) => writeln!(out, "{}{}",msg.head(msg.flags),flags)?,
+                Err(e) => writeln!(out, "{}{}",msg.head(msg.flags),e)?,
+            }
+            // 找不到挂载时（例如在别的挂载命名空间里）只给出f_flags
+            if let Some(mount) = mountinfo::find(&iter.filename){
+                writeln!(out, "{}{}",msg.head(msg.mount),mount.mount_options)?;
+                writeln!(out, "{}{}",msg.head(msg.super_block),mount.super_options)?;
+            }
 
-        }print!("\n");
+        }
+        writeln!(out)
     }
 }
 
-fn get_file_stat(filename : String) -> FileStat{
+/// df的算法：已用的是总块数减去空闲块数，使用率的分母是已用加上普通用户可用的，
+/// 所以给root保留的块不算在内，百分比向上取整
+struct Usage{
+    size : u64,     //以下三项单位为字节
+    used : u64,
+    avail : u64,
+    files : u64,
+    iused : u64,
+    ffree : u64,
+}
+
+impl Usage{
+    fn new(filestat : &statfs) -> Self{
+        let frsize = filestat.f_frsize as u64;
+        let used = filestat.f_blocks.saturating_sub(filestat.f_bfree);
+        Usage{
+            size : filestat.f_blocks*frsize,
+            used : used*frsize,
+            avail : filestat.f_bavail*frsize,
+            files : filestat.f_files,
+            iused : filestat.f_files.saturating_sub(filestat.f_ffree),
+            ffree : filestat.f_ffree,
+        }
+    }
+
+    /// 没有可用空间的统计时（例如proc）为None
+    fn use_percent(&self) -> Option<u64>{
+        let total = self.used + self.avail;
+        if total==0 { None } else { Some((self.used*100).div_ceil(total)) }
+    }
+
+    fn inode_use_percent(&self) -> Option<u64>{
+        if self.files==0 { None } else { Some((self.iused*100).div_ceil(self.files)) }
+    }
+}
+
+/// 解析后的格式串中的一段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatToken{
+    Text(String),           //原样输出的文字
+    Directive(char),        //%后面跟着的指令字符
+    Named(String, Option<String>), //%{名字}或者%{名字:参数}
+    Cond(Predicate, Vec<FormatToken>, Vec<FormatToken>), //条件段，条件成立时输出前一部分，否则输出后一部分
+}
+
+/// 把格式串拆成文字和指令，"%%"表示一个'%'，"%}"表示一个'}'，末尾单独的'%'原样输出
+/// %{名字:参数}形式的指令到第一个'}'为止
+/// 条件段有两种写法：%{?条件:文字} 中的条件到第一个使它完整的':'为止，文字到对应的'}'为止，其中可以再有指令；
+/// %{if 条件}…%{else}…%{end} 可以嵌套，%{else}可以省略
+/// 没有结束的 %{、没有名字的 %{}、不认识的条件和不成对的 if/else/end 是错误，不认识的指令不算错误，输出时原样给出
+pub fn parse_format(command : &str) -> Result<Vec<FormatToken>,StatError>{
+    let mut parser = FormatParser{ rest : command };
+    match parser.sequence(false)?{
+        (tokens, FormatStop::Eof) => Ok(tokens),
+        _ => Err(StatError::InvalidFormat),
+    }
+}
+
+/// 一段格式在哪里结束
+enum FormatStop{
+    Eof,        //格式串结束
+    Close,      //%{?条件:文字} 的'}'
+    Else,       //%{else}
+    End,        //%{end}
+}
+
+struct FormatParser<'a>{
+    rest : &'a str,     //还没有解析的部分
+}
+
+impl FormatParser<'_>{
+    /// 解析到格式串结束或者遇到条件段的结束标记为止，in_short表示在 %{?条件:文字} 的文字中
+    fn sequence(&mut self, in_short : bool) -> Result<(Vec<FormatToken>, FormatStop),StatError>{
+        let mut tokens = Vec::new();
+        let mut text = String::new();
+        let stop = loop{
+            let mut chars = self.rest.chars();
+            let Some(ch) = chars.next() else{
+                break FormatStop::Eof;
+            };
+            self.rest = chars.as_str();
+            if ch=='}' && in_short{
+                break FormatStop::Close;
+            }
+            if ch!='%'{
+                text.push(ch);
+                continue;
+            }
+            let Some(next) = chars.next() else{
+                text.push('%');
+                continue;
+            };
+            self.rest = chars.as_str();
+            match next{
+                '%' | '}' => {
+                    text.push(next);
+                    continue;
+                },
+                '{' => {},
+                directive => {
+                    flush_text(&mut tokens, &mut text);
+                    tokens.push(FormatToken::Directive(directive));
+                    continue;
+                },
+            }
+            flush_text(&mut tokens, &mut text);
+            if let Some(rest) = self.rest.strip_prefix('?'){
+                let (predicate, colon) = Predicate::parse_prefix(rest)?;
+                self.rest = &rest[colon+1..];
+                let (then, FormatStop::Close) = self.sequence(true)? else{
+                    return Err(StatError::InvalidFormat);
+                };
+                tokens.push(FormatToken::Cond(predicate, then, Vec::new()));
+                continue;
+            }
+            let end = self.rest.find('}').ok_or(StatError::InvalidFormat)?;
+            let body = &self.rest[..end];
+            self.rest = &self.rest[end+1..];
+            match body{
+                "else" => break FormatStop::Else,
+                "end" => break FormatStop::End,
+                _ => {},
+            }
+            if let Some(condition) = body.strip_prefix("if "){
+                let predicate = Predicate::parse(condition)?;
+                let (then, stop) = self.sequence(false)?;
+                let otherwise = match stop{
+                    FormatStop::End => Vec::new(),
+                    FormatStop::Else => match self.sequence(false)?{
+                        (otherwise, FormatStop::End) => otherwise,
+                        _ => return Err(StatError::InvalidFormat),
+                    },
+                    _ => return Err(StatError::InvalidFormat),
+                };
+                tokens.push(FormatToken::Cond(predicate, then, otherwise));
+                continue;
+            }
+            if body.is_empty() || body.starts_with(':'){
+                return Err(StatError::InvalidFormat);
+            }
+            tokens.push(match body.split_once(':'){
+                Some((name, arg)) => FormatToken::Named(name.to_string(), Some(arg.to_string())),
+                None => FormatToken::Named(body.to_string(), None),
+            });
+        };
+        flush_text(&mut tokens, &mut text);
+        Ok((tokens, stop))
+    }
+}
+
+fn flush_text(tokens : &mut Vec<FormatToken>, text : &mut String){
+    if !text.is_empty(){
+        tokens.push(FormatToken::Text(std::mem::take(text)));
+    }
+}
+
+/// 输出文件格式串中一个指令对应的内容
+fn write_file_directive<W : Write>(out : &mut W, ch : char, iter : &FileStat) -> io::Result<()>{
+    let filestat = &iter.stat;
+    match ch{
+        'a' => write!(out,"{}",FilePermission::new(filestat.st_mode).output_num()),
+        'A' => write!(out,"{}{}",FilePermission::new(filestat.st_mode).output_char(),iter.acl_mark()),
+        'b' => write!(out,"{}",filestat.st_blocks),
+        'B' => write!(out,"{}",sparse::BLOCK_UNIT),
+        'C' => write!(out,"rstat: failed to get security context of 'dfs.sh': No data available \n?",),//这里因为没找到对应的接口，先暂缓
+        'd' => write!(out,"{}",filestat.st_dev),
+        'D' => write!(out,"{:x}",filestat.st_dev),
+        'f' => write!(out,"{:x}",filestat.st_mode),
+        'F' => write!(out,"{}",FileType::get_file_type(filestat.st_mode)),
+        'g' => write!(out,"{}",filestat.st_gid),
+        'G' => write!(out,"{}",get_groupname_with_id(filestat.st_gid)),
+        'h' => write!(out,"{}",filestat.st_nlink),
+        'i' => write!(out,"{}",filestat.st_ino),
+        'm' => write!(out,"/"),//这里的功能没有实际完成，思路是读取/proc/mounts进行比对，有的话就输出相应的，否则就是'/'
+        'n' => write!(out,"{}",iter.filename),
+        'N' => {
+            if iter.blinker{
+                write!(out,"'{}' -> '{}'",iter.filename,iter.oriname)
+            }
+            else{
+                write!(out,"'{}'",iter.filename)
+            }
+        },
+        'o' => write!(out,"{}",filestat.st_blksize),
+        's' => write!(out,"{}",filestat.st_size),
+        't' => write!(out,"!t!"),//未完成
+        'T' => write!(out,"!T!"),
+        'u' => write!(out,"{}",filestat.st_uid),
+        'U' => write!(out,"{}",get_username_with_id(filestat.st_uid)),
+        'w' => write!(out,"-"),
+        'W' => write!(out,"-"),
+        'x' => write!(out,"{}",get_time_utc2local(filestat.st_atime, filestat.st_atime_nsec)),
+        'X' => write!(out,"{}",filestat.st_atime_nsec),
+        'y' => write!(out,"{}",get_time_utc2local(filestat.st_atime, filestat.st_mtime_nsec)),
+        'Y' => write!(out,"{}",filestat.st_mtime),
+        'z' => write!(out,"{}",get_time_utc2local(filestat.st_atime, filestat.st_ctime_nsec)),
+        'Z' => write!(out,"{}",filestat.st_ctime),
+        _   => write!(out,"{}",ch),
+    }
+}
+
+/// 输出文件格式串中一个%{名字:参数}指令对应的内容
+/// now为计算 :ago 和 :age 时的"现在"
+fn write_file_named<W : Write>(out : &mut W, name : &str, arg : Option<&str>, iter : &FileStat, units : SizeUnits, now : i64) -> io::Result<()>{
+    match (name, arg){
+        // 属性不存在时什么都不输出，方便脚本判断
+        ("xattr", Some(attr)) => {
+            if let Some(value) = iter.xattr(attr){
+                write!(out,"{}",xattr::display_value(value))?;
+            }
+            Ok(())
+        },
+        // 没有ACL时同样什么都不输出
+        ("acl", None) | ("acl", Some("access")) | ("acl", Some("default")) => {
+            let attr = if arg==Some("default") { acl::DEFAULT } else { acl::ACCESS };
+            if let Some(acl) = iter.xattr(attr).and_then(Acl::parse){
+                write!(out,"{}",acl.short_text())?;
+            }
+            Ok(())
+        },
+        ("flags", None) | ("flags", Some("long")) => {
+            if let Some(flags) = iter.inode_flags(){
+                let text = if arg.is_some() { flags.names() } else { flags.letters() };
+                write!(out,"{}",text)?;
+            }
+            Ok(())
+        },
+        ("project", None) => {
+            if let Some(projid) = iter.inode_flags().and_then(|f| f.projid){
+                write!(out,"{}",projid)?;
+            }
+            Ok(())
+        },
+        ("name", None) => write!(out,"{}",iter.filename),
+        // 不是符号链接时什么都不输出
+        ("target", None) => {
+            if iter.blinker{
+                write!(out,"{}",iter.oriname)?;
+            }
+            Ok(())
+        },
+        ("type", None) => write!(out,"{}",FileType::get_file_type(iter.stat.st_mode)),
+        ("type", Some("char")) => write!(out,"{}",FileType::get_file_type(iter.stat.st_mode).output_short()),
+        // 默认和 ls -l 一样带上类型字母
+        ("mode", None) | ("mode", Some("symbolic")) => write!(out,"{}{}",
+            FileType::get_file_type(iter.stat.st_mode).output_short(),FilePermission::new(iter.stat.st_mode).output_char()),
+        ("mode", Some("octal")) => write!(out,"{}",FilePermission::new(iter.stat.st_mode).output_num()),
+        ("mode", Some("hex")) => write!(out,"{:x}",iter.stat.st_mode),
+        ("owner", None) => write!(out,"{}",get_username_with_id(iter.stat.st_uid)),
+        ("owner", Some("id")) | ("uid", None) => write!(out,"{}",iter.stat.st_uid),
+        ("group", None) => write!(out,"{}",get_groupname_with_id(iter.stat.st_gid)),
+        ("group", Some("id")) | ("gid", None) => write!(out,"{}",iter.stat.st_gid),
+        ("inode", None) => write!(out,"{}",iter.stat.st_ino),
+        ("links", None) => write!(out,"{}",iter.stat.st_nlink),
+        ("dev", None) => write!(out,"{}",iter.stat.st_dev),
+        ("dev", Some("hex")) => write!(out,"{:x}",iter.stat.st_dev),
+        // 设备号只对字符设备和块设备有意义，其他文件什么都不输出
+        ("major", None) | ("minor", None) => {
+            if matches!(FileType::get_file_type(iter.stat.st_mode), FileType::Block | FileType::Character){
+                let rdev = iter.stat.st_rdev;
+                write!(out,"{}",if name=="major" { get_dev_major(rdev) } else { get_dev_minor(rdev) })?;
+            }
+            Ok(())
+        },
+        ("size", None) => write!(out,"{}",iter.stat.st_size),
+        ("blocks", None) => write!(out,"{}",iter.stat.st_blocks),
+        ("ioblock", None) => write!(out,"{}",iter.stat.st_blksize),
+        ("atime", _) | ("mtime", _) | ("ctime", _) => {
+            let (sec, nsec) = match name{
+                "atime" => (iter.stat.st_atime, iter.stat.st_atime_nsec),
+                "mtime" => (iter.stat.st_mtime, iter.stat.st_mtime_nsec),
+                _ => (iter.stat.st_ctime, iter.stat.st_ctime_nsec),
+            };
+            match style::time_directive(sec, nsec, arg, now){
+                Some(text) => write!(out,"{}",text),
+                None => write_unknown_named(out, name, arg),
+            }
+        },
+        // 和 %w、%W 一样，创建时间还取不到
+        ("btime", Some("epoch")) => write!(out,"0"),
+        ("btime", _) => write!(out,"-"),
+        ("alloc", None) => write!(out,"{}",sparse::allocated(&iter.stat)),
+        // :h 按 --si 的选择换算，没有给出时按1024
+        ("size", Some("h")) => write!(out,"{}",units.or_binary().format(iter.stat.st_size as u64)),
+        ("alloc", Some("h")) => write!(out,"{}",units.or_binary().format(sparse::allocated(&iter.stat) as u64)),
+        ("sparseness", None) => write!(out,"{:.4}",sparse::sparseness(&iter.stat)),
+        ("caps", None) => {
+            if let Some(caps) = iter.xattr(capability::XATTR_NAME).and_then(FileCaps::parse){
+                write!(out,"{}",caps.to_text())?;
+            }
+            Ok(())
+        },
+        // 只判断普通文件，读不了时什么都不输出
+        ("mime", None) | ("magic", None) => {
+            if FileType::get_file_type(iter.stat.st_mode)==FileType::Regular{
+                if let Ok(content) = magic::sniff(&iter.filename, iter.follow){
+                    write!(out,"{}",if name=="mime" { content.mime } else { content.description })?;
+                }
+            }
+            Ok(())
+        },
+        // 只有用到时才读文件内容，不是普通文件或者读不了时什么都不输出
+        (name, None) => match Algorithm::from_name(name){
+            Some(algorithm) => {
+                if FileType::get_file_type(iter.stat.st_mode)==FileType::Regular{
+                    if let Ok(sum) = digest::file_digest(&iter.filename, algorithm){
+                        write!(out,"{}",sum)?;
+                    }
+                }
+                Ok(())
+            },
+            None => write_unknown_named(out, name, arg),
+        },
+        _ => write_unknown_named(out, name, arg),
+    }
+}
+
+/// 不认识的指令原样输出
+fn write_unknown_named<W : Write>(out : &mut W, name : &str, arg : Option<&str>) -> io::Result<()>{
+    match arg{
+        Some(arg) => write!(out,"%{{{}:{}}}",name,arg),
+        None => write!(out,"%{{{}}}",name),
+    }
+}
+
+/// 输出文件系统格式串中一个指令对应的内容
+fn write_filesystem_directive<W : Write>(out : &mut W, ch : char, iter : &FileStat) -> io::Result<()>{
+    let filestat = &iter.statfs;
+    match ch{
+        'a' => write!(out,"{}",filestat.f_bavail),
+        'b' => write!(out,"{}",filestat.f_blocks),
+        'c' => write!(out,"{}",filestat.f_files),
+        'd' => write!(out,"{}",filestat.f_ffree),
+        'f' => write!(out,"{}",filestat.f_bfree),
+        'i' => write!(out,"{:x}",431254),//老问题，暂时还没找到提取出来的方法
+        'n' => write!(out,"{}",iter.filename),
+        's' => write!(out,"{}",filestat.f_bsize),//这里可能搞反了
+        'S' => write!(out,"{}",filestat.f_frsize),
+        't' => write!(out,"{:x}",filestat.f_type),
+        'T' => write!(out,"{}",filestat.f_type),
+        _   => write!(out,"{}",ch),
+    }
+}
+
+/// 文件系统的 %{名字} 和 %{名字:h}，块数换算时给出对应的字节数
+fn write_filesystem_named<W : Write>(out : &mut W, name : &str, arg : Option<&str>, iter : &FileStat, units : SizeUnits) -> io::Result<()>{
+    let filestat = &iter.statfs;
+    match (name, arg){
+        ("flags", None) => {
+            if let Ok(flags) = mountinfo::mount_flags(&iter.filename){
+                write!(out,"{}",flags)?;
+            }
+            return Ok(());
+        },
+        // 挂载找不到时什么都不输出
+        ("options", None) | ("options", Some("mount")) | ("options", Some("super")) => {
+            if let Some(mount) = mountinfo::find(&iter.filename){
+                let options = match arg{
+                    Some("mount") => mount.mount_options,
+                    Some(_) => mount.super_options,
+                    None => mount.options(),
+                };
+                write!(out,"{}",options)?;
+            }
+            return Ok(());
+        },
+        _ => {},
+    }
+    let (value, is_blocks) = match name{
+        "blocks" => (filestat.f_blocks, true),
+        "bfree" => (filestat.f_bfree, true),
+        "bavail" => (filestat.f_bavail, true),
+        "files" => (filestat.f_files, false),
+        "ffree" => (filestat.f_ffree, false),
+        _ => return write_unknown_named(out, name, arg),
+    };
+    match arg{
+        None => write!(out,"{}",value),
+        Some("h") if is_blocks => write!(out,"{}",units.or_binary().format(value*filestat.f_frsize as u64)),
+        Some("h") => write!(out,"{}",units.or_binary().format(value)),
+        _ => write_unknown_named(out, name, arg),
+    }
+}
+
+pub fn get_file_stat(filename : String) -> FileStat{
     unsafe{
         let mut kstat = get_new_stat();
-        let ret = lstat(filename.as_ptr().cast(),(&mut kstat) as *mut stat);
+        let ret = lstat(cpath(&filename).as_ptr(),(&mut kstat) as *mut stat);
         if ret==-1{
             FileStat{
-                filename: filename,
+                filename,
                 stat: get_new_stat(),
                 statfs: get_new_statfs(),
                 blinker: false, 
                 oriname: String::new(), 
                 alive: false,
                 err_info: std::io::Error::last_os_error().to_string(),
+                follow: false,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
         else{
@@ -431,6 +1654,9 @@ fn get_file_stat(filename : String) -> FileStat{
                 } ,
                 alive: true,
                 err_info: String::new(),
+                follow: false,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
     }
@@ -439,16 +1665,19 @@ fn get_file_stat(filename : String) -> FileStat{
 fn get_file_stat_link(filename: String) ->FileStat{
     unsafe{
         let mut klstat = get_new_stat();
-        let ret = stat(filename.as_ptr().cast(),(&mut klstat) as *mut stat);
+        let ret = stat(cpath(&filename).as_ptr(),(&mut klstat) as *mut stat);
         if ret==-1{
             FileStat{
-                filename: filename,
+                filename,
                 stat: get_new_stat(),
                 statfs: get_new_statfs(),
                 blinker: false, 
                 oriname: String::new(), 
                 alive: false,
                 err_info: std::io::Error::last_os_error().to_string(),
+                follow: true,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
         else{
@@ -460,6 +1689,9 @@ fn get_file_stat_link(filename: String) ->FileStat{
                 oriname: String::new(),
                 alive: true,
                 err_info: String::new(),
+                follow: true,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
     }
@@ -469,27 +1701,33 @@ fn get_file_stat_link(filename: String) ->FileStat{
 fn get_file_statfs(filename : String) ->FileStat{
     unsafe{
         let mut kstatfs = get_new_statfs();
-        let ret = statfs(filename.as_ptr().cast(),(&mut kstatfs) as *mut statfs);
+        let ret = statfs(cpath(&filename).as_ptr(),(&mut kstatfs) as *mut statfs);
         if ret==-1{
             FileStat{
-                filename: filename,
+                filename,
                 stat: get_new_stat(),
                 statfs: get_new_statfs(),
                 blinker: false,
                 oriname: String::new(), 
                 alive: false,
                 err_info: std::io::Error::last_os_error().to_string(),
+                follow: true,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
         else{
             FileStat{
-                filename: filename,
+                filename,
                 stat: get_new_stat(),
                 statfs: kstatfs,
                 blinker: false, //实际上这里有可能提供的本身是一个链接，但是文件系统中无关是不是链接，故而无需考虑这个项的值
                 oriname: String::new(), 
                 alive: true,
                 err_info: String::new(),
+                follow: true,
+                xattrs: Vec::new(),
+                flags: None,
             }
         }
     }
@@ -498,19 +1736,20 @@ fn get_file_statfs(filename : String) ->FileStat{
 
 
 fn get_link_oriname(linkname: String) -> String{
-    unsafe{
-        let mut namebuf : [i8; 50] = [0;50];
-        let charbuf = namebuf.as_mut_ptr().cast();
-        let ret=libc::readlink(linkname.as_ptr().cast(),charbuf,50);
-        if ret!=-1 && ret<50{
-            let res=std::ffi::CStr::from_ptr(charbuf);
-            return format!("{}",res.to_str().unwrap());
-        }
-        println!("err");
-        return linkname;
+    // 链接目标的长度没有上限，直接交给标准库按需分配缓冲区
+    match std::fs::read_link(&linkname){
+        Ok(target) => target.to_string_lossy().into_owned(),
+        Err(_) => linkname,
     }
 }
 
+/// 在Rust下String末尾是不带'\0'的,但是c的数组末尾以这个结束,调用c接口前要先转换一下
+/// 命令行参数中不会出现'\0'，转换失败时给出空串，让接口自己报错
+#[inline]
+pub fn cpath(filename : &str) -> CString{
+    CString::new(filename).unwrap_or_default()
+}
+
 
 // fn get_pwd_win() -> String{
 //     let cwd=std::env::current_dir().unwrap();
@@ -520,51 +1759,44 @@ fn get_link_oriname(linkname: String) -> String{
 #[inline]
 pub fn get_new_stat() -> stat{
     unsafe{
-        return std::mem::zeroed::<libc::stat>();
+        std::mem::zeroed::<libc::stat>()
     }
 }
 
 #[inline]
 pub fn get_new_statfs() -> statfs{
     unsafe{
-        return std::mem::zeroed::<libc::statfs>();
+        std::mem::zeroed::<libc::statfs>()
     }
 }
 
-#[allow(dead_code)]
 pub fn get_dev_major(devno: u64) -> u32{
-    unsafe{
-        return libc::major(devno);
-    }
+    libc::major(devno)
 }
 
-#[allow(dead_code)]
 pub fn get_dev_minor(devno: u64) -> u32{
-    unsafe{
-        return libc::minor(devno);
-    }
-}
-
-/// Encoding of the file mode.  
-/// #define	__S_IFMT	0170000	/* These bits determine file type.  
-/// File types. 
-/// #define	__S_IFDIR	0040000	 Directory. 
-// #define	__S_IFCHR	0020000	 Character device.  
-// #define	__S_IFBLK	0060000	 Block device. 
-// #define	__S_IFREG	0100000	 Regular file.  
-// #define	__S_IFIFO	0010000	 FIFO.  
-// #define	__S_IFLNK	0120000	 Symbolic link.  
-// #define	__S_IFSOCK	0140000	 Socket.  
-
-/// Protection bits.  
-
-/// #define	__S_ISUID	04000	 Set user ID on execution.  
-/// #define	__S_ISGID	02000	 Set group ID on execution.  
-/// #define	__S_ISVTX	01000	 Save swapped text after use (sticky).  
-///#define	__S_IREAD	0400	 Read by owner. 
-/// #define	__S_IWRITE	0200	 Write by owner.  
-/// #define	__S_IEXEC	0100	 Execute by owner.  
-/// 
+    libc::minor(devno)
+}
+
+// Encoding of the file mode.  
+// #define    __S_IFMT    0170000    /* These bits determine file type.  
+// File types. 
+// #define    __S_IFDIR    0040000     Directory. 
+// #define    __S_IFCHR    0020000     Character device.  
+// #define    __S_IFBLK    0060000     Block device. 
+// #define    __S_IFREG    0100000     Regular file.  
+// #define    __S_IFIFO    0010000     FIFO.  
+// #define    __S_IFLNK    0120000     Symbolic link.  
+// #define    __S_IFSOCK    0140000     Socket.  
+
+// Protection bits.  
+// #define    __S_ISUID    04000     Set user ID on execution.  
+// #define    __S_ISGID    02000     Set group ID on execution.  
+// #define    __S_ISVTX    01000     Save swapped text after use (sticky).  
+// #define    __S_IREAD    0400     Read by owner. 
+// #define    __S_IWRITE    0200     Write by owner.  
+// #define    __S_IEXEC    0100     Execute by owner.  
+// 
 #[allow(non_camel_case_types)]
 type mode_t = u32;
 pub const S_IFIFO: mode_t = 4096;
@@ -592,15 +1824,13 @@ pub const S_IFMT: mode_t = 61440;
 
 #[inline]
 pub fn is_symbolic_link(mode: u32) ->bool{
-    if mode & (S_IFMT) == S_IFLNK{
-        return true;
-    }
-    return false;
+    mode & (S_IFMT) == S_IFLNK
 }
 
 
 
-enum FileType{
+#[derive(PartialEq, Eq)]
+pub(crate) enum FileType{
     Block,
     Character,
     Directory,
@@ -611,6 +1841,23 @@ enum FileType{
     Unknown,
 }
 
+impl FileType{
+    /// 默认输出中使用的类型名，随语言变化；%F和JSON中仍用Display给出的英文名
+    pub fn localized(&self, messages : &Messages) -> &'static str{
+        let index = match self{
+            FileType::Block => 0,
+            FileType::Character => 1,
+            FileType::Directory => 2,
+            FileType::Link => 3,
+            FileType::Regular => 4,
+            FileType::Socket => 5,
+            FileType::Pipe => 6,
+            FileType::Unknown => 7,
+        };
+        messages.file_types[index]
+    }
+}
+
 impl Display for FileType{
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self{
@@ -664,7 +1911,7 @@ impl  FileType {
         }
     } 
 }
-struct FilePermission{
+pub(crate) struct FilePermission{
     //filetype: FileType, 
     owner : &'static str,    //rwx
     group : &'static str,
@@ -736,10 +1983,10 @@ impl FilePermission{
             group: group_per,
             other: other_per,
         //    special: spe_per,
-            ngroup : ngroup,
-            nother : nother,
-            nspe : nspe,
-            nowner : nowner,
+            ngroup,
+            nother,
+            nspe,
+            nowner,
         }
     }
 
@@ -753,29 +2000,35 @@ impl FilePermission{
 }
 
 pub fn get_username_with_id(uid: u32) -> String{
+    lookup_username(uid).unwrap_or_else(|| String::from("nullptr"))
+}
+
+pub fn get_groupname_with_id(gid: u32) -> String{
+    lookup_groupname(gid).unwrap_or_else(|| String::from("nullptr"))
+}
+
+/// 用户不存在时为None
+pub fn lookup_username(uid: u32) -> Option<String>{
     unsafe{
-        //let userinfo=std::mem::zeroed::<libc::passwd>();
         let userinfo = libc::getpwuid(uid);
         if userinfo.is_null(){
-            format!("nullptr")
+            None
         }
         else{
-            let sde = std::ffi::CStr::from_ptr((*userinfo).pw_name);
-            format!("{}",sde.to_str().unwrap())
+            Some(std::ffi::CStr::from_ptr((*userinfo).pw_name).to_string_lossy().into_owned())
         }
     }
 }
 
-pub fn get_groupname_with_id(gid: u32) -> String{
+/// 组不存在时为None
+pub fn lookup_groupname(gid: u32) -> Option<String>{
     unsafe{
-        //let userinfo=std::mem::zeroed::<libc::passwd>();
         let groupinfo = libc::getgrgid(gid);
         if groupinfo.is_null(){
-            format!("nullptr")
+            None
         }
         else{
-            let sde = std::ffi::CStr::from_ptr((*groupinfo).gr_name);
-            format!("{}",sde.to_str().unwrap())
+            Some(std::ffi::CStr::from_ptr((*groupinfo).gr_name).to_string_lossy().into_owned())
         }
     }
 }
@@ -786,54 +2039,244 @@ pub fn get_time_utc2local(tc: i64, tn: i64) -> String{
     lc.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// 带纳秒的本地时间，用于比较和报告变化
+pub fn get_time_full(tc: i64, tn: i64) -> String{
+    format!("{}.{:09}",get_time_utc2local(tc, tn),tn)
+}
+
 #[inline]
-pub fn output_more_help(){
-    println!("The helper for format mode ------ or you can just use 'rstat --help'
-The valid format sequences for files (without --file-system):
-
-    %a   access rights in octal (note '#' and '0' printf flags)
-    %A   access rights in human readable form
-    %b   number of blocks allocated (see %B)
-    %B   the size in bytes of each block reported by %b
-    %C   SELinux security context string
-    %d   device number in decimal
-    %D   device number in hex
-    %f   raw mode in hex
-    %F   file type
-    %g   group ID of owner
-    %G   group name of owner
-    %h   number of hard links
-    %i   inode number
-    %m   mount point
-    %n   file name
-    %N   quoted file name with dereference if symbolic link
-    %o   optimal I/O transfer size hint
-    %s   total size, in bytes
-    %t   major device type in hex, for character/block device special files
-    %T   minor device type in hex, for character/block device special files
-    %u   user ID of owner
-    %U   user name of owner
-    %w   time of file birth, human-readable; - if unknown
-    %W   time of file birth, seconds since Epoch; 0 if unknown
-    %x   time of last access, human-readable
-    %X   time of last access, seconds since Epoch
-    %y   time of last data modification, human-readable
-    %Y   time of last data modification, seconds since Epoch
-    %z   time of last status change, human-readable
-    %Z   time of last status change, seconds since Epoch
-  
-  Valid format sequences for file systems:
-  
-    %a   free blocks available to non-superuser
-    %b   total data blocks in file system
-    %c   total file nodes in file system
-    %d   free file nodes in file system
-    %f   free blocks in file system
-    %i   file system ID in hex
-    %l   maximum length of filenames
-    %n   file name
-    %s   block size (for faster transfers)
-    %S   fundamental block size (for block counts)
-    %t   file system type in hex
-    %T   file system type in human readable form");
+pub fn output_more_help<W : Write>(out : &mut W, messages : &Messages) -> io::Result<()>{
+    writeln!(out, "{}",messages.more_help)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::i18n::EN;
+
+    /// 临时目录下一个内容为 "hello" 的文件，用完后删掉整个目录
+    struct Sample{
+        dir : std::path::PathBuf,
+        path : String,
+    }
+
+    impl Drop for Sample{
+        fn drop(&mut self){
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn sample(name : &str) -> Sample{
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        std::fs::write(&path, b"hello").unwrap();
+        Sample{ path : path.to_string_lossy().into_owned(), dir }
+    }
+
+    /// 不受环境中语言设置影响的选项
+    fn options() -> OptionSelected{
+        let mut opt = OptionSelected::default();
+        opt.messages = &EN;
+        opt
+    }
+
+    fn render(opt : &OptionSelected, files : &[&str]) -> String{
+        let format = parse_format(&opt.output_inf).unwrap();
+        let getter = opt.getter(opt.extras(&format));
+        let filestats : Vec<FileStat> = files.iter().map(|f| getter(f.to_string())).collect();
+        let mut buf : Vec<u8> = Vec::new();
+        opt.render(&mut buf, filestats.into_iter(), &format, "+00:00").unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn render_default(){
+        let sample = sample("default");
+        let path = &sample.path;
+        let text = render(&options(), &[path]);
+        assert!(text.starts_with(&format!("  File: {}\n", path)), "{}", text);
+        assert!(text.contains("  Size: 5 "), "{}", text);
+        assert!(text.contains("regular file"), "{}", text);
+        // 不要求时不读文件内容
+        assert!(!text.contains("Content:"), "{}", text);
+    }
+
+    #[test]
+    fn render_default_magic(){
+        let sample = sample("magic");
+        let mut opt = options();
+        opt.bmagic = true;
+        let text = render(&opt, &[&sample.path]);
+        // 标签比其它行首标签长一个字符，不补空白
+        assert!(text.contains("\nContent: ASCII text (text/plain; charset=us-ascii)\n"), "{}", text);
+    }
+
+    /// 默认输出在所有文件之后还有一个空行
+    #[test]
+    fn render_default_missing_file(){
+        let text = render(&options(), &["/nonexistent/rstat-test"]);
+        assert_eq!(text, "stat: cannot stat '/nonexistent/rstat-test': No such file or directory (os error 2)\n\n");
+    }
+
+    #[test]
+    fn render_terse(){
+        let sample = sample("terse");
+        let path = &sample.path;
+        let mut opt = options();
+        opt.bterse = true;
+        let text = render(&opt, &[path]);
+        assert!(text.starts_with(&format!("{} 5 ", path)), "{}", text);
+        assert_eq!(text.lines().count(), 1);
+    }
+
+    #[test]
+    fn render_format(){
+        let sample = sample("format");
+        let path = &sample.path;
+        let mut opt = options();
+        opt.bfilter = true;
+        opt.output_inf = String::from("%n|%s|%{type}|%%");
+        let text = render(&opt, &[path, path]);
+        let line = format!("{}|5|regular file|%\n", path);
+        assert_eq!(text, format!("{}{}", line, line));
+    }
+
+    #[test]
+    fn render_json(){
+        let sample = sample("json");
+        let path = &sample.path;
+        let mut opt = options();
+        opt.bjson = true;
+        let text = render(&opt, &[path, "/nonexistent/rstat-test"]);
+        assert!(text.starts_with("[\n  {"), "{}", text);
+        assert!(text.ends_with("\n]\n"), "{}", text);
+        assert!(text.contains(&format!("\"name\":\"{}\"", path)), "{}", text);
+        assert!(text.contains("\"type\":\"regular file\""), "{}", text);
+        assert!(text.contains("\"size\":5"), "{}", text);
+        assert!(text.contains("{\"name\":\"/nonexistent/rstat-test\",\"error\":\"No such file or directory (os error 2)\"}"), "{}", text);
+    }
+
+    #[test]
+    fn diff_uses_values_from_stat_time(){
+        let sample = sample("diff");
+        let old = get_file_stat(sample.path.clone()).load(Extras::ALL);
+        let mut new = old.clone();
+        new.xattrs.push((String::from("user.test"), b"1".to_vec()));
+        new.flags = Some(InodeFlags{ flags : 0x10, complete : true, projid : None });
+        // 文件本身没有变化，比较的只能是各自保存下来的值
+        let diffs = old.diff(&new);
+        let fields : Vec<&str> = diffs.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(fields, ["flags", "xattr:user.test"]);
+        assert_eq!(diffs[1].old, "-");
+        assert!(new.diff(&new.clone()).is_empty());
+    }
+
+    #[test]
+    fn extras_of_format(){
+        let extras = |format : &str| Extras::of_format(&parse_format(format).unwrap());
+        assert_eq!(extras("%n %s"), Extras::default());
+        assert_eq!(extras("%A"), Extras{ xattrs : true, flags : false });
+        assert_eq!(extras("%{project}"), Extras{ xattrs : false, flags : true });
+        assert_eq!(extras("%{acl:default}%{xattr:user.a}"), Extras{ xattrs : true, flags : false });
+        assert_eq!(extras("%{if type=f}%{flags}%{end}"), Extras{ xattrs : false, flags : true });
+    }
+
+    fn text(s : &str) -> FormatToken{
+        FormatToken::Text(s.to_string())
+    }
+
+    fn cond(condition : &str, then : Vec<FormatToken>, otherwise : Vec<FormatToken>) -> FormatToken{
+        FormatToken::Cond(Predicate::parse(condition).unwrap(), then, otherwise)
+    }
+
+    /// %{?条件:文字} 的分隔符不是引号和时间中的':'
+    #[test]
+    fn parse_format_short_condition_separator(){
+        assert_eq!(parse_format("%{?file:x}").unwrap(), [cond("file", vec![text("x")], vec![])]);
+        assert_eq!(parse_format("%{?name='a:b':yes}").unwrap(), [cond("name='a:b'", vec![text("yes")], vec![])]);
+        assert_eq!(parse_format("%{?mtime>2024-01-01T10:00:00:new}").unwrap(),
+            [cond("mtime>2024-01-01T10:00:00", vec![text("new")], vec![])]);
+        // 文字中的':'原样保留
+        assert_eq!(parse_format("%{?link: -> %{target}}").unwrap(),
+            [cond("link", vec![text(" -> "), FormatToken::Named(String::from("target"), None)], vec![])]);
+    }
+
+    #[test]
+    fn parse_format_nesting(){
+        assert_eq!(parse_format("%{if dir}d%{else}%{if empty}e%{?size>1k:%{?exec:x}}%{end}%{end}!").unwrap(), [
+            cond("dir", vec![text("d")], vec![
+                cond("empty", vec![text("e"), cond("size>1k", vec![cond("exec", vec![text("x")], vec![])], vec![])], vec![]),
+            ]),
+            text("!"),
+        ]);
+        assert_eq!(parse_format("%{?file:a%}b}").unwrap(), [cond("file", vec![text("a}b")], vec![])]);
+    }
+
+    #[test]
+    fn parse_format_unbalanced(){
+        for format in ["%{else}", "%{end}", "%{if file}x", "%{if file}%{else}%{else}%{end}",
+                       "%{if file}%{end}%{end}", "%{?file:x", "%{?file}", "%{?file:%{else}}", "%{if file}%{?dir:x%{end}}"]{
+            assert!(parse_format(format).is_err(), "{}", format);
+        }
+        // 条件写错时给出表达式中的位置
+        assert_eq!(parse_format("%{?size>1x:big}").err().unwrap().to_string(), "invalid expression: column 6: invalid size '1x'");
+    }
+
+    #[test]
+    fn render_conditions(){
+        let sample = sample("conditions");
+        let mut opt = options();
+        opt.bfilter = true;
+        opt.output_inf = String::from("%{?file:F}%{if size>4}%{?name='data'::big}%{else}small%{end}");
+        assert_eq!(render(&opt, &[&sample.path]), "F:big\n");
+    }
+
+    fn read(args : &[&str]) -> Result<OptionSelected, StatError>{
+        let mut opt = options();
+        let args = std::iter::once("rstat").chain(args.iter().copied()).map(String::from).collect();
+        opt.readoption(args).map(|_| opt)
+    }
+
+    #[test]
+    fn now_option(){
+        assert_eq!(read(&["--now=@100", "f"]).unwrap().now, 100);
+        assert_eq!(read(&["--now", "1970-01-01T00:01:40Z", "f"]).unwrap().now, 100);
+        assert!(matches!(read(&["--now=soon", "f"]), Err(StatError::InvalidValue)));
+        assert!(matches!(read(&["f", "--now"]), Err(StatError::MissingValue)));
+    }
+
+    #[test]
+    fn preset_conflicts_with_format(){
+        // 不管先后都不能和 -c 一起用，在找预设之前就报错
+        assert!(matches!(read(&["--preset", "x", "-c", "|%s", "f"]), Err(StatError::WrongOption)));
+        assert!(matches!(read(&["-c", "|%s", "--preset", "x", "f"]), Err(StatError::WrongOption)));
+        assert!(matches!(read(&["--preset=x", "--format=FORMAT", "%s", "f"]), Err(StatError::WrongOption)));
+        assert!(matches!(read(&["--preset=x", "--preset=y", "f"]), Err(StatError::WrongOption)));
+    }
+
+    #[test]
+    fn short_option_values(){
+        // 值可以紧跟在短选项后面，也可以是下一个参数
+        assert_eq!(read(&["-j4", "f"]).unwrap().jobs, 4);
+        assert_eq!(read(&["-j", "4", "f"]).unwrap().jobs, 4);
+        let opt = read(&["-tj8", "f"]).unwrap();
+        assert!(opt.bterse);
+        assert_eq!(opt.jobs, 8);
+        assert_eq!(read(&["-o/tmp/out", "f"]).unwrap().outfile.as_deref(), Some("/tmp/out"));
+        assert_eq!(read(&["-o", "/tmp/out", "f"]).unwrap().outfile.as_deref(), Some("/tmp/out"));
+        assert_eq!(read(&["-to-", "f"]).unwrap().outfile.as_deref(), Some("-"));
+        assert!(matches!(read(&["-j0", "f"]), Err(StatError::InvalidValue)));
+        assert!(matches!(read(&["f", "-j"]), Err(StatError::MissingValue)));
+    }
+
+    #[test]
+    fn render_relative_times(){
+        let sample = sample("relative");
+        let mtime = std::os::unix::fs::MetadataExt::mtime(&std::fs::metadata(&sample.path).unwrap());
+        let mut opt = read(&["-c", "%{mtime:ago}|%{mtime:age}", &format!("--now=@{}", mtime+120), &sample.path]).unwrap();
+        assert_eq!(render(&opt, &[&sample.path]), "2 minutes ago|120\n");
+        opt = read(&["--relative-times", &format!("--now=@{}", mtime-3600), &sample.path]).unwrap();
+        assert!(render(&opt, &[&sample.path]).contains("\nModify: in 1 hour\n"));
+    }
 }
\ No newline at end of file
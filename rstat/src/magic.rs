@@ -0,0 +1,405 @@
+//! 按文件内容判断文件的类型，类似 file(1)
+//! 只读取文件开头的一块，用内置的特征表匹配，不依赖系统的magic数据库，离线也能用
+//! 依次判断：空文件、ELF、特征表、脚本（#!）、文本编码，都不是时为 data
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+
+/// 判断时读取的文件开头的大小，tar的特征在257字节处
+const HEAD : usize = 4096;
+
+/// 判断的结果
+pub struct Content{
+    pub mime : String,          //MIME类型，例如 application/gzip
+    pub description : String,   //file(1)风格的说明，例如 gzip compressed data
+}
+
+impl Content{
+    fn new(mime : &str, description : &str) -> Self{
+        Content{ mime : mime.to_string(), description : description.to_string() }
+    }
+}
+
+/// 一条特征：所有 (偏移, 字节) 都吻合时即为该类型
+struct Signature{
+    magic : &'static [(usize, &'static [u8])],
+    mime : &'static str,
+    description : &'static str,
+}
+
+/// 内置的特征表，按顺序匹配，更具体的要放在前面（例如deb在ar之前）
+const SIGNATURES : &[Signature] = &[
+    // 图像
+    Signature{ magic : &[(0, b"\x89PNG\r\n\x1a\n")], mime : "image/png", description : "PNG image data" },
+    Signature{ magic : &[(0, b"\xff\xd8\xff")], mime : "image/jpeg", description : "JPEG image data" },
+    Signature{ magic : &[(0, b"GIF87a")], mime : "image/gif", description : "GIF image data, version 87a" },
+    Signature{ magic : &[(0, b"GIF89a")], mime : "image/gif", description : "GIF image data, version 89a" },
+    Signature{ magic : &[(0, b"RIFF"), (8, b"WEBP")], mime : "image/webp", description : "RIFF (little-endian) data, Web/P image" },
+    Signature{ magic : &[(0, b"II*\0")], mime : "image/tiff", description : "TIFF image data, little-endian" },
+    Signature{ magic : &[(0, b"MM\0*")], mime : "image/tiff", description : "TIFF image data, big-endian" },
+    // 音视频
+    Signature{ magic : &[(0, b"RIFF"), (8, b"WAVE")], mime : "audio/x-wav", description : "RIFF (little-endian) data, WAVE audio" },
+    Signature{ magic : &[(0, b"RIFF"), (8, b"AVI ")], mime : "video/x-msvideo", description : "RIFF (little-endian) data, AVI" },
+    Signature{ magic : &[(0, b"ID3")], mime : "audio/mpeg", description : "Audio file with ID3 tag" },
+    Signature{ magic : &[(0, b"fLaC")], mime : "audio/flac", description : "FLAC audio bitstream data" },
+    Signature{ magic : &[(0, b"OggS")], mime : "audio/ogg", description : "Ogg data" },
+    Signature{ magic : &[(4, b"ftyp")], mime : "video/mp4", description : "ISO Media" },
+    Signature{ magic : &[(0, b"\x1a\x45\xdf\xa3")], mime : "video/x-matroska", description : "Matroska data" },
+    // 文档
+    Signature{ magic : &[(0, b"%PDF-")], mime : "application/pdf", description : "PDF document" },
+    Signature{ magic : &[(0, b"%!PS")], mime : "application/postscript", description : "PostScript document text" },
+    Signature{ magic : &[(0, b"{\\rtf")], mime : "text/rtf", description : "Rich Text Format data" },
+    Signature{ magic : &[(0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1")], mime : "application/x-ole-storage", description : "Composite Document File V2 Document" },
+    Signature{ magic : &[(0, b"SQLite format 3\0")], mime : "application/vnd.sqlite3", description : "SQLite 3.x database" },
+    // 压缩和归档
+    Signature{ magic : &[(0, b"\x1f\x8b")], mime : "application/gzip", description : "gzip compressed data" },
+    Signature{ magic : &[(0, b"BZh")], mime : "application/x-bzip2", description : "bzip2 compressed data" },
+    Signature{ magic : &[(0, b"\xfd7zXZ\0")], mime : "application/x-xz", description : "XZ compressed data" },
+    Signature{ magic : &[(0, b"\x28\xb5\x2f\xfd")], mime : "application/zstd", description : "Zstandard compressed data" },
+    Signature{ magic : &[(0, b"\x04\x22\x4d\x18")], mime : "application/x-lz4", description : "LZ4 compressed data" },
+    Signature{ magic : &[(0, b"7z\xbc\xaf\x27\x1c")], mime : "application/x-7z-compressed", description : "7-zip archive data" },
+    Signature{ magic : &[(0, b"Rar!\x1a\x07")], mime : "application/vnd.rar", description : "RAR archive data" },
+    Signature{ magic : &[(0, b"PK\x03\x04"), (30, b"[Content_Types].xml")], mime : "application/vnd.openxmlformats-officedocument", description : "Microsoft OOXML" },
+    Signature{ magic : &[(0, b"PK\x03\x04")], mime : "application/zip", description : "Zip archive data" },
+    Signature{ magic : &[(0, b"PK\x05\x06")], mime : "application/zip", description : "Zip archive data (empty)" },
+    Signature{ magic : &[(257, b"ustar")], mime : "application/x-tar", description : "POSIX tar archive" },
+    Signature{ magic : &[(0, b"070701")], mime : "application/x-cpio", description : "ASCII cpio archive (SVR4 with no CRC)" },
+    Signature{ magic : &[(0, b"070707")], mime : "application/x-cpio", description : "ASCII cpio archive (pre-SVR4 or odc)" },
+    Signature{ magic : &[(0, b"!<arch>\ndebian-binary")], mime : "application/vnd.debian.binary-package", description : "Debian binary package" },
+    Signature{ magic : &[(0, b"!<arch>\n")], mime : "application/x-archive", description : "current ar archive" },
+    Signature{ magic : &[(0, b"\xed\xab\xee\xdb")], mime : "application/x-rpm", description : "RPM" },
+    Signature{ magic : &[(0, b"hsqs")], mime : "application/octet-stream", description : "Squashfs filesystem, little endian" },
+    // 其他可执行格式
+    Signature{ magic : &[(0, b"\0asm")], mime : "application/wasm", description : "WebAssembly (wasm) binary module" },
+    Signature{ magic : &[(0, b"\xca\xfe\xba\xbe")], mime : "application/x-java-applet", description : "compiled Java class data" },
+    Signature{ magic : &[(0, b"\xcf\xfa\xed\xfe")], mime : "application/x-mach-binary", description : "Mach-O 64-bit executable" },
+    Signature{ magic : &[(0, b"\xce\xfa\xed\xfe")], mime : "application/x-mach-binary", description : "Mach-O executable" },
+    Signature{ magic : &[(0, b"MZ")], mime : "application/vnd.microsoft.portable-executable", description : "MS-DOS executable" },
+];
+
+/// ELF e_machine 和名字，只列出常见的
+const MACHINES : &[(u16, &str)] = &[
+    (2, "SPARC"),
+    (3, "Intel 80386"),
+    (8, "MIPS"),
+    (20, "PowerPC"),
+    (21, "64-bit PowerPC"),
+    (22, "IBM S/390"),
+    (40, "ARM"),
+    (43, "SPARC V9"),
+    (62, "x86-64"),
+    (183, "ARM aarch64"),
+    (243, "RISC-V"),
+    (258, "LoongArch"),
+];
+
+const PT_DYNAMIC : u32 = 2;
+const PT_INTERP : u32 = 3;
+
+/// 判断一个普通文件的内容类型
+/// follow为false时不跟随链接，调用者应当只对普通文件调用
+pub fn sniff(filename : &str, follow : bool) -> io::Result<Content>{
+    let nofollow = if follow { 0 } else { libc::O_NOFOLLOW };
+    let open = |flags| OpenOptions::new().read(true).custom_flags(flags).open(filename);
+    // 用O_NOATIME打开，只是查看不应该改掉访问时间；不是文件的属主时没有权限这样做，退回到普通的打开方式
+    let mut file = match open(nofollow | libc::O_NOATIME){
+        Err(e) if e.raw_os_error()==Some(libc::EPERM) => open(nofollow)?,
+        res => res?,
+    };
+    let mut head = Vec::with_capacity(HEAD);
+    (&mut file).take(HEAD as u64).read_to_end(&mut head)?;
+
+    if head.is_empty(){
+        return Ok(Content::new("inode/x-empty", "empty"));
+    }
+    if head.starts_with(b"\x7fELF"){
+        if let Some(content) = elf(&file, &head){
+            return Ok(content);
+        }
+    }
+    if let Some(sig) = SIGNATURES.iter().find(|s| s.magic.iter().all(|(at, bytes)| matches_at(&head, *at, bytes))){
+        return Ok(Content::new(sig.mime, sig.description));
+    }
+    // 文本只看读到的这一块，末尾可能截断了一个UTF-8字符
+    let truncated = head.len()==HEAD;
+    let Some((encoding, charset)) = text_encoding(&head, truncated) else{
+        return Ok(Content::new("application/octet-stream", "data"));
+    };
+    if let Some(line) = head.strip_prefix(b"#!"){
+        let line = String::from_utf8_lossy(line.split(|&b| b==b'\n').next().unwrap_or_default()).into_owned();
+        if let Some(interp) = interpreter(&line){
+            return Ok(Content{
+                mime : script_mime(&interp),
+                description : format!("{} script, {} text executable",interp,encoding),
+            });
+        }
+    }
+    let (mime, kind) = markup(&head);
+    Ok(Content{
+        mime : format!("{}; charset={}",mime,charset),
+        description : format!("{}{} text",kind,encoding),
+    })
+}
+
+fn matches_at(head : &[u8], at : usize, bytes : &[u8]) -> bool{
+    head.get(at..at+bytes.len())==Some(bytes)
+}
+
+/// 解析ELF头，给出位数、字节序、类型、架构和链接方式
+fn elf(file : &File, head : &[u8]) -> Option<Content>{
+    let class64 = match head.get(4)?{
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let little = match head.get(5)?{
+        1 => true,
+        2 => false,
+        _ => return None,
+    };
+    let u16_at = |b : &[u8], at : usize| -> Option<u16>{
+        let v : [u8; 2] = b.get(at..at+2)?.try_into().ok()?;
+        Some(if little { u16::from_le_bytes(v) } else { u16::from_be_bytes(v) })
+    };
+    let u32_at = |b : &[u8], at : usize| -> Option<u32>{
+        let v : [u8; 4] = b.get(at..at+4)?.try_into().ok()?;
+        Some(if little { u32::from_le_bytes(v) } else { u32::from_be_bytes(v) })
+    };
+    let u64_at = |b : &[u8], at : usize| -> Option<u64>{
+        let v : [u8; 8] = b.get(at..at+8)?.try_into().ok()?;
+        Some(if little { u64::from_le_bytes(v) } else { u64::from_be_bytes(v) })
+    };
+
+    let e_type = u16_at(head, 16)?;
+    let e_machine = u16_at(head, 18)?;
+    let (phoff, phentsize, phnum) = if class64{
+        (u64_at(head, 32)?, u16_at(head, 54)?, u16_at(head, 56)?)
+    }
+    else{
+        (u32_at(head, 28)? as u64, u16_at(head, 42)?, u16_at(head, 44)?)
+    };
+
+    // 程序头一般紧跟在ELF头后面，但不保证在读到的那一块里，所以直接按偏移读
+    let mut interp = None;
+    let mut dynamic = false;
+    for i in 0..phnum as u64{
+        let mut ph = vec![0u8; phentsize as usize];
+        if phentsize<8 || file.read_exact_at(&mut ph, phoff + i*phentsize as u64).is_err(){
+            break;
+        }
+        match u32_at(&ph, 0)?{
+            PT_DYNAMIC => dynamic = true,
+            PT_INTERP => {
+                let (offset, size) = if class64{
+                    (u64_at(&ph, 8)?, u64_at(&ph, 32)?)
+                }
+                else{
+                    (u32_at(&ph, 4)? as u64, u32_at(&ph, 16)? as u64)
+                };
+                let mut path = vec![0u8; size.min(4096) as usize];
+                if file.read_exact_at(&mut path, offset).is_ok(){
+                    let end = path.iter().position(|&b| b==0).unwrap_or(path.len());
+                    interp = Some(String::from_utf8_lossy(&path[..end]).into_owned());
+                }
+            },
+            _ => {},
+        }
+    }
+
+    let (kind, mime) = match (e_type, interp.is_some()){
+        (1, _) => ("relocatable", "application/x-object"),
+        (2, _) => ("executable", "application/x-executable"),
+        (3, true) => ("pie executable", "application/x-pie-executable"),
+        (3, false) => ("shared object", "application/x-sharedlib"),
+        (4, _) => ("core file", "application/x-coredump"),
+        _ => ("unknown type", "application/x-executable"),
+    };
+    let machine = MACHINES.iter().find(|(m, _)| *m==e_machine)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("machine {}",e_machine));
+    let mut description = format!("ELF {}-bit {} {}, {}",
+        if class64 { 64 } else { 32 },if little { "LSB" } else { "MSB" },kind,machine);
+    // 可重定位文件和core文件谈不上链接方式
+    match (e_type, interp){
+        (2, Some(interp)) | (3, Some(interp)) => description.push_str(&format!(", dynamically linked, interpreter {}",interp)),
+        (2, None) | (3, None) if dynamic => description.push_str(", dynamically linked"),
+        (2, None) => description.push_str(", statically linked"),
+        _ => {},
+    }
+    Some(Content::new(mime, &description))
+}
+
+/// 文本的编码，不是文本时返回None，第二项为MIME中的charset
+fn text_encoding(head : &[u8], truncated : bool) -> Option<(&'static str, &'static str)>{
+    if head.starts_with(b"\xef\xbb\xbf"){
+        return Some(("UTF-8 (with BOM)", "utf-8"));
+    }
+    if head.starts_with(b"\xff\xfe"){
+        return Some(("UTF-16, little-endian", "utf-16le"));
+    }
+    if head.starts_with(b"\xfe\xff"){
+        return Some(("UTF-16, big-endian", "utf-16be"));
+    }
+    // 除了常见的空白和ESC以外的控制字符都说明是二进制数据
+    let control = |b : u8| b<0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x08 | 0x1b) || b==0x7f;
+    if head.iter().any(|&b| control(b)){
+        return None;
+    }
+    if head.is_ascii(){
+        return Some(("ASCII", "us-ascii"));
+    }
+    match std::str::from_utf8(head){
+        Ok(_) => Some(("Unicode text, UTF-8", "utf-8")),
+        // 只在截断处出错时仍然是UTF-8
+        Err(e) if truncated && e.error_len().is_none() => Some(("Unicode text, UTF-8", "utf-8")),
+        Err(_) => Some(("ISO-8859", "iso-8859-1")),
+    }
+}
+
+/// 常见的标记语言，返回MIME类型和说明的前缀
+fn markup(head : &[u8]) -> (&'static str, &'static str){
+    let start = head.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(0);
+    let text = String::from_utf8_lossy(&head[start..head.len().min(start+64)]).to_ascii_lowercase();
+    if text.starts_with("<!doctype html") || text.starts_with("<html"){
+        ("text/html", "HTML document, ")
+    }
+    else if text.starts_with("<?xml"){
+        ("text/xml", "XML document, ")
+    }
+    else{
+        ("text/plain", "")
+    }
+}
+
+/// 从 #! 行中取出解释器的名字，/usr/bin/env 时取后面的命令
+fn interpreter(line : &str) -> Option<String>{
+    let mut words = line.split_whitespace();
+    let first = words.next()?;
+    let name = first.rsplit('/').next()?;
+    if name=="env"{
+        // 跳过 env -S 之类的选项
+        let cmd = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+        return Some(cmd.rsplit('/').next()?.to_string());
+    }
+    Some(name.to_string())
+}
+
+fn script_mime(interp : &str) -> String{
+    // python3.11 和 python 是同一种脚本
+    let base = interp.trim_end_matches(|c : char| c.is_ascii_digit() || c=='.');
+    match base{
+        "sh" | "bash" | "dash" | "zsh" | "ksh" | "ash" => String::from("text/x-shellscript"),
+        "python" => String::from("text/x-script.python"),
+        "perl" => String::from("text/x-perl"),
+        "ruby" => String::from("text/x-ruby"),
+        "node" | "nodejs" => String::from("application/javascript"),
+        _ => format!("text/x-script.{}",base),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// 把data写到临时文件里再判断，返回 (mime, description)
+    fn sniff_bytes(name : &str, data : &[u8]) -> (String, String){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-magic-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data");
+        std::fs::write(&path, data).unwrap();
+        let content = sniff(path.to_str().unwrap(), false).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        (content.mime, content.description)
+    }
+
+    fn pair(mime : &str, description : &str) -> (String, String){
+        (mime.to_string(), description.to_string())
+    }
+
+    #[test]
+    fn signatures(){
+        assert_eq!(sniff_bytes("png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), pair("image/png", "PNG image data"));
+        assert_eq!(sniff_bytes("gzip", b"\x1f\x8b\x08\0"), pair("application/gzip", "gzip compressed data"));
+        assert_eq!(sniff_bytes("webp", b"RIFF\0\0\0\0WEBPVP8 "), pair("image/webp", "RIFF (little-endian) data, Web/P image"));
+        // 更具体的特征在前面
+        assert_eq!(sniff_bytes("deb", b"!<arch>\ndebian-binary   "), pair("application/vnd.debian.binary-package", "Debian binary package"));
+        assert_eq!(sniff_bytes("ar", b"!<arch>\nfoo.o/          "), pair("application/x-archive", "current ar archive"));
+        // 不在开头的特征
+        let mut tar = vec![0u8; 512];
+        tar[..4].copy_from_slice(b"file");
+        tar[257..263].copy_from_slice(b"ustar\0");
+        assert_eq!(sniff_bytes("tar", &tar), pair("application/x-tar", "POSIX tar archive"));
+    }
+
+    /// 最小的ELF头，没有程序头
+    fn elf_header(class64 : bool, little : bool, e_type : u16, machine : u16) -> Vec<u8>{
+        let mut head = vec![0u8; 64];
+        head[..4].copy_from_slice(b"\x7fELF");
+        head[4] = if class64 { 2 } else { 1 };
+        head[5] = if little { 1 } else { 2 };
+        head[6] = 1;
+        let u16_bytes = |v : u16| if little { v.to_le_bytes() } else { v.to_be_bytes() };
+        head[16..18].copy_from_slice(&u16_bytes(e_type));
+        head[18..20].copy_from_slice(&u16_bytes(machine));
+        head
+    }
+
+    #[test]
+    fn elf_headers(){
+        assert_eq!(sniff_bytes("elf-exec", &elf_header(true, true, 2, 62)),
+                   pair("application/x-executable", "ELF 64-bit LSB executable, x86-64, statically linked"));
+        assert_eq!(sniff_bytes("elf-so", &elf_header(true, true, 3, 183)),
+                   pair("application/x-sharedlib", "ELF 64-bit LSB shared object, ARM aarch64"));
+        assert_eq!(sniff_bytes("elf-rel", &elf_header(false, false, 1, 8)),
+                   pair("application/x-object", "ELF 32-bit MSB relocatable, MIPS"));
+        assert_eq!(sniff_bytes("elf-core", &elf_header(true, true, 4, 999)),
+                   pair("application/x-coredump", "ELF 64-bit LSB core file, machine 999"));
+        // 正在运行的测试程序本身
+        let exe = sniff("/proc/self/exe", true).unwrap();
+        assert!(exe.description.starts_with("ELF "), "{}", exe.description);
+    }
+
+    #[test]
+    fn scripts(){
+        assert_eq!(sniff_bytes("sh", b"#!/bin/sh\necho hi\n"), pair("text/x-shellscript", "sh script, ASCII text executable"));
+        assert_eq!(sniff_bytes("env", b"#!/usr/bin/env -S python3 -u\nprint(1)\n"),
+                   pair("text/x-script.python", "python3 script, ASCII text executable"));
+        assert_eq!(sniff_bytes("lua", b"#!/usr/local/bin/lua5.4\n"), pair("text/x-script.lua", "lua5.4 script, ASCII text executable"));
+        // 二进制内容即使以 #! 开头也不是脚本
+        assert_eq!(sniff_bytes("binary-shebang", b"#!/bin/sh\n\0\x01"), pair("application/octet-stream", "data"));
+    }
+
+    #[test]
+    fn text_and_binary(){
+        assert_eq!(sniff_bytes("empty", b""), pair("inode/x-empty", "empty"));
+        assert_eq!(sniff_bytes("ascii", b"hello\n"), pair("text/plain; charset=us-ascii", "ASCII text"));
+        assert_eq!(sniff_bytes("utf8", "你好\n".as_bytes()), pair("text/plain; charset=utf-8", "Unicode text, UTF-8 text"));
+        assert_eq!(sniff_bytes("latin1", b"caf\xe9\n"), pair("text/plain; charset=iso-8859-1", "ISO-8859 text"));
+        assert_eq!(sniff_bytes("html", b"\n<!DOCTYPE html>\n<html></html>\n"),
+                   pair("text/html; charset=us-ascii", "HTML document, ASCII text"));
+        assert_eq!(sniff_bytes("data", b"\0\x01\x02\x03"), pair("application/octet-stream", "data"));
+        // 只在读到的那一块末尾截断的UTF-8字符仍然是UTF-8文本
+        let mut long = "中".repeat(HEAD/3+1).into_bytes();
+        long.truncate(HEAD+1);
+        assert_eq!(sniff_bytes("truncated", &long).0, "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn sniff_keeps_atime(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-magic", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script");
+        std::fs::write(&path, b"#!/bin/sh\necho hi\n").unwrap();
+        // 访问时间早于修改时间，relatime下普通的读取也会更新它
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        File::options().write(true).open(&path).unwrap()
+            .set_times(std::fs::FileTimes::new().set_accessed(old)).unwrap();
+
+        let content = sniff(path.to_str().unwrap(), false).unwrap();
+        let atime = std::fs::metadata(&path).unwrap().accessed().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(content.mime, "text/x-shellscript");
+        assert_eq!(atime, old);
+    }
+}
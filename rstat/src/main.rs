@@ -1,30 +1,55 @@
+mod acl;
+mod capability;
+mod color;
+mod compare;
+mod config;
+mod digest;
+mod fiemap;
+mod human;
+mod i18n;
+mod iflags;
+mod json;
 mod kstat;
+mod magic;
+mod manifest;
+mod mountinfo;
+mod mtree;
+mod pool;
+mod predicate;
+mod sparse;
+mod style;
+mod watch;
+mod xattr;
 
 use std::env;
 use clap::{Command,arg};
 
+/// 出错时的退出码，1留给 --compare、--verify、--mtree-check 的"有差异"
+const ERROR_EXIT : i32 = 2;
+
 fn main() {
-    let mut cli = cli();
-    let mut _command = cli.clone().get_matches();
+    let _command = cli().get_matches();
     let args:Vec<String> = env::args().collect();
 
     let mut filesoption=kstat::OptionSelected::default();
 
-    match filesoption.readoption(args){
-        Ok(_) =>{} ,
-        Err(e) => {
-            println!("{:?}",e);
-            cli.print_help().unwrap();
-            std::process::exit(0);
-        },
-    };
+    if let Err(e) = filesoption.readoption(args){
+        eprintln!("rstat: {}",e);
+        eprintln!("Try 'rstat --help' for more information.");
+        std::process::exit(ERROR_EXIT);
+    }
 
     //println!("{:?}",filesoption);
 
 
     match filesoption.output(){
-        Ok(_) => {},
-        Err(e) => println!("{:?}",e),
+        Ok(code) => std::process::exit(code),
+        // 输出端提前关闭（例如接在head后面）时直接退出即可
+        Err(kstat::StatError::Io(e)) if e.kind()==std::io::ErrorKind::BrokenPipe => {},
+        Err(e) => {
+            eprintln!("rstat: {}",e);
+            std::process::exit(ERROR_EXIT);
+        },
     };
 }
 
@@ -41,9 +66,41 @@ fn cli() -> Command<'static>{
             //windows下没有文件系统可以调用，故而不存在这个选项
             arg!(filter: -c [format] "use the specified FORMAT instead of the default;\noutput a newline after each use of FORMAT"),
             arg!(pfilter: --"printf=FORMAT"   "like --format, but interpret backslash escapes,\nand do not output a mandatory trailing newline;\nif you want a newline, include \\n' in FORMAT"),
+            // -h 留给 --human-readable，帮助只保留长选项
+            arg!(help: --"help"  "print help information"),
+            arg!(humanreadable: -h --"human-readable"  "print sizes in powers of 1024 (e.g. 1.5K, 23M)"),
+            arg!(si: --"si"  "like --human-readable, but use powers of 1000"),
+            arg!(usage: --"usage"  "with --file-system, show the size, used and available space\nand inodes like df(1), with the source device and mount point"),
+            arg!(allmounts: --"all-mounts"  "with --file-system, report every mounted file system from\n/proc/self/mountinfo; bind mounts are listed once"),
+            arg!(fstype: --"type" [TYPES]  "with --all-mounts, only report the comma separated file\nsystem TYPES, e.g. --type=ext4,xfs"),
+            arg!(excludetype: --"exclude-type" [TYPES]  "with --all-mounts, skip the file system TYPES,\ne.g. --exclude-type=tmpfs"),
+            arg!(color: --"color" [WHEN]  "colour the default output: file names by type from LS_COLORS,\nspecial permission bits and unknown owners; WHEN is 'always'\n(default), 'auto' or 'never'; 'auto' honours NO_COLOR").require_equals(true).min_values(0),
+            arg!(where: --"where" [EXPR]  "only print the files matching EXPR, e.g.\n'type=f && size>1M && mtime<-7d && user!=root'; see -H"),
+            arg!(relativetimes: --"relative-times"  "show times in the default output as their age,\ne.g. 3 minutes ago, or 'in 2 hours' for times in the future"),
+            arg!(now: --"now" [TIMESTAMP]  "compute ages for --relative-times and %{mtime:ago} from TIMESTAMP:\n @SECONDS, RFC 3339 or local 'YYYY-MM-DD[ HH:MM:SS]'"),
+            arg!(preset: --"preset" [NAME]  "use the format preset NAME from ~/.config/rstat/config.toml\n(or $RSTAT_CONFIG) like -c"),
+            arg!(timestyle: --"time-style" [STYLE]  "show times in the default output as full-iso (default),\nlong-iso, iso or +FORMAT"),
+            arg!(quotingstyle: --"quoting-style" [STYLE]  "quote file names in the default output: literal (default),\nshell, shell-always, escape or c"),
             arg!(terse: -t  --"terse"       "print the information in terse form"),
+            arg!(json: --"json"  "print the information as a JSON array, one object per file"),
+            arg!(jobs: -j --"jobs" [N]  "stat the files with N worker threads (default 1);\nhelps when stat waits on I/O, e.g. NFS or a cold cache;\noutput keeps the order of the operands"),
+            arg!(outfile: -o --"output" [FILE]  "write the output to FILE instead of standard output"),
+            arg!(watch: --"watch"  "keep watching the files and print a line for every changed field"),
+            arg!(interval: --"interval" [SECS]  "with --watch, re-check the files at least every SECS seconds\n(default 1), for file systems that do not deliver inotify events"),
+            arg!(compare: --"compare"  "compare the status of exactly two files field by field;\nexit with 1 if they differ"),
+            arg!(ignore: --"ignore" [FIELDS]  "with --compare, skip the comma separated FIELDS,\ne.g. --ignore=atime,ctime"),
+            arg!(snapshot: --"snapshot" [MANIFEST]  "record the status of the files (directories recursively)\ninto MANIFEST"),
+            arg!(verify: --"verify" [MANIFEST]  "re-stat the files recorded in MANIFEST and report added,\nremoved and changed entries; exit with 1 on any change"),
+            arg!(holes: --"holes"  "also list the data and hole ranges of regular files"),
+            arg!(extents: --"extents"  "also list the FIEMAP extents of regular files with their\nphysical offsets, flags and the number of shared bytes"),
+            arg!(xattrs: --"xattrs"  "also list the extended attributes of each file"),
+            arg!(flags: --"flags"  "also list the inode flags (lsattr) and project ID of each file"),
+            arg!(magic: --"magic"  "also identify the content of regular files like file(1);\nthis reads the start of each file"),
+            arg!(digest: --"digest" [ALGS]  "with --json, --snapshot or --mtree, also record the digests of\nregular files; ALGS is a comma separated list of md5, sha1,\nsha256, blake3 and crc32 (default sha256)").require_equals(true).min_values(0),
+            arg!(mtree: --"mtree"  "print a BSD mtree(5) specification of the file trees"),
+            arg!(mtreecheck: --"mtree-check" [SPEC]  "check the file tree (default '.') against the mtree\nspecification SPEC; exit with 1 on any mismatch"),
             arg!(mhelper: -H --"more-help" "print more help information"),
-            arg!(<filename> ... "the files you want to stat"),
+            arg!([filename] ... "the files you want to stat"),
         ])
 }
 
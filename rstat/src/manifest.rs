@@ -0,0 +1,333 @@
+//! --snapshot / --verify：把一批文件的信息记录成清单，之后重新获取并报告新增、删除和变化的条目
+//!
+//! 清单是纯文本，每行一个条目，目录会递归记录，同一目录下按名字排序，所以同样的树总会得到同样的清单：
+//!
+//! ```text
+//! #rstat-manifest 1
+//! #digest md5 sha256
+//! #root /srv/app
+//! /srv/app type=dir mode=0755 uid=0 gid=0 mtime=1652201055.000000000
+//! /srv/app/run.sh type=file mode=0755 uid=0 gid=0 size=120 mtime=1652201055.000000000 md5=... sha256=...
+//! /srv/app/current type=link mode=0777 uid=0 gid=0 size=5 mtime=1652201055.000000000 link=v1.2
+//! ```
+//!
+//! 路径和取值中的空白、'\'、'='、'#'以及不可打印的字节写成'\'加三位八进制，'#'开头的行是注释
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use crate::digest::{self, Algorithm};
+use crate::kstat::{get_file_stat, FileStat, StatError};
+
+const HEADER : &str = "#rstat-manifest 1";
+
+/// 一个条目的所有键值，按写入清单的顺序排列
+type Keys = Vec<(&'static str, String)>;
+
+/// 对roots中的每一项（目录会递归）生成清单写到out中
+/// digests为每个普通文件要记录的内容摘要，可以为空
+pub fn snapshot<W : Write>(out : &mut W, roots : &[String], digests : &[Algorithm]) -> io::Result<()>{
+    writeln!(out, "{}",HEADER)?;
+    if !digests.is_empty(){
+        let names : Vec<&str> = digests.iter().map(|a| a.name()).collect();
+        writeln!(out, "#digest {}",names.join(" "))?;
+    }
+    for root in roots{
+        writeln!(out, "#root {}",escape(root))?;
+    }
+    // 不存在的根只记在 #root 里，之后出现了就是新增
+    for root in roots{
+        walk(root, &mut |filestat|{
+            if !filestat.alive{
+                return Ok(());
+            }
+            write_entry(out, &filestat.filename, &entry_keys(&filestat, digests))
+        })?;
+    }
+    Ok(())
+}
+
+/// 读取清单，重新获取其中记录的树，报告所有差异
+/// 返回是否没有任何变化；清单打不开或者不是清单（包括空文件）时是错误，不能当成没有变化
+pub fn verify<W : Write>(out : &mut W, manifest : &str) -> Result<bool,StatError>{
+    let reader = BufReader::new(File::open(manifest).map_err(|e| with_path(manifest, e))?);
+    let mut roots = Vec::new();
+    let mut digests = Vec::new();
+    let mut recorded : BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+    let mut lines = 0;
+    for (lineno, line) in reader.lines().enumerate(){
+        let line = line.map_err(|e| with_path(manifest, e))?;
+        lines += 1;
+        if lineno==0 && line!=HEADER{
+            return Err(StatError::InvalidManifest);
+        }
+        if let Some(root) = line.strip_prefix("#root "){
+            roots.push(unescape(root));
+        }
+        else if let Some(names) = line.strip_prefix("#digest "){
+            digests = names.split_whitespace()
+                .map(Algorithm::from_name)
+                .collect::<Option<Vec<_>>>()
+                .ok_or(StatError::InvalidManifest)?;
+        }
+        else if line.starts_with('#') || line.is_empty(){
+            continue;
+        }
+        else{
+            let (path, keys) = parse_entry(&line).ok_or(StatError::InvalidManifest)?;
+            recorded.insert(path, keys);
+        }
+    }
+
+    if lines==0{
+        return Err(StatError::InvalidManifest);
+    }
+
+    let mut clean = true;
+    for root in &roots{
+        walk(root, &mut |filestat|{
+            let keys : BTreeMap<String, String> = entry_keys(&filestat, &digests).into_iter()
+                .map(|(k,v)| (k.to_string(), v))
+                .collect();
+            // 没有任何键的条目表示文件不存在（旧版本的清单会这样记录不存在的根）
+            match recorded.remove(&filestat.filename).filter(|old| !old.is_empty()){
+                None if keys.is_empty() => Ok(()),
+                None => {
+                    clean = false;
+                    writeln!(out, "added: {}",filestat.filename)
+                },
+                Some(_) if keys.is_empty() => {
+                    clean = false;
+                    writeln!(out, "removed: {}",filestat.filename)
+                },
+                Some(old) => {
+                    for (key, old_value, new_value) in diff_keys(&old, &keys){
+                        clean = false;
+                        writeln!(out, "changed: {}: {}: {} -> {}",filestat.filename,key,old_value,new_value)?;
+                    }
+                    Ok(())
+                },
+            }
+        })?;
+    }
+    // 剩下的就是清单里有但现在已经不存在的
+    for path in recorded.iter().filter(|(_, keys)| !keys.is_empty()).map(|(path, _)| path){
+        clean = false;
+        writeln!(out, "removed: {}",path)?;
+    }
+    Ok(clean)
+}
+
+/// 深度优先遍历，先给出目录本身，再按名字顺序给出目录下的内容
+/// 只用lstat，不会跟着链接进入别的目录
+pub fn walk<F>(root : &str, f : &mut F) -> io::Result<()>
+where
+    F: FnMut(FileStat) -> io::Result<()>,
+{
+    let filestat = get_file_stat(root.to_string());
+    let is_dir = filestat.alive && filestat.stat.st_mode & libc::S_IFMT==libc::S_IFDIR;
+    f(filestat)?;
+    if !is_dir{
+        return Ok(());
+    }
+    // 目录读不了时只记录目录本身
+    let Ok(dir) = std::fs::read_dir(root) else{
+        return Ok(());
+    };
+    let mut names : Vec<String> = dir.filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    for name in names{
+        let child = if root.ends_with('/') { format!("{}{}",root,name) } else { format!("{}/{}",root,name) };
+        walk(&child, f)?;
+    }
+    Ok(())
+}
+
+/// 一个条目要记录的信息，不存在的文件没有任何键
+fn entry_keys(filestat : &FileStat, digests : &[Algorithm]) -> Keys{
+    if !filestat.alive{
+        return vec![];
+    }
+    let st = &filestat.stat;
+    let ftype = st.st_mode & libc::S_IFMT;
+    let mut keys : Keys = vec![
+        ("type", type_keyword(st.st_mode).to_string()),
+        ("mode", format!("{:04o}",st.st_mode & 0o7777)),
+        ("uid", st.st_uid.to_string()),
+        ("gid", st.st_gid.to_string()),
+    ];
+    // 目录的大小和文件系统有关，没有比较的意义
+    if ftype!=libc::S_IFDIR{
+        keys.push(("size", st.st_size.to_string()));
+    }
+    keys.push(("mtime", format!("{}.{:09}",st.st_mtime,st.st_mtime_nsec)));
+    if filestat.blinker{
+        keys.push(("link", filestat.oriname.clone()));
+    }
+    if !digests.is_empty() && ftype==libc::S_IFREG{
+        match digest::file_digests(&filestat.filename, digests){
+            Ok(values) => keys.extend(digests.iter().map(|a| a.name()).zip(values)),
+            Err(e) => keys.extend(digests.iter().map(|a| (a.name(), format!("error({})",e)))),
+        }
+    }
+    keys
+}
+
+/// mtree(5)中使用的类型名
+pub fn type_keyword(mode : u32) -> &'static str{
+    match mode & libc::S_IFMT{
+        libc::S_IFBLK => "block",
+        libc::S_IFCHR => "char",
+        libc::S_IFDIR => "dir",
+        libc::S_IFIFO => "fifo",
+        libc::S_IFLNK => "link",
+        libc::S_IFREG => "file",
+        libc::S_IFSOCK => "socket",
+        _ => "unknown",
+    }
+}
+
+fn write_entry<W : Write>(out : &mut W, path : &str, keys : &Keys) -> io::Result<()>{
+    write!(out, "{}",escape(path))?;
+    for (key, value) in keys{
+        write!(out, " {}={}",key,escape(value))?;
+    }
+    writeln!(out)
+}
+
+fn parse_entry(line : &str) -> Option<(String, BTreeMap<String, String>)>{
+    let mut words = line.split(' ');
+    let path = unescape(words.next()?);
+    let mut keys = BTreeMap::new();
+    for word in words{
+        let (key, value) = word.split_once('=')?;
+        keys.insert(key.to_string(), unescape(value));
+    }
+    Some((path, keys))
+}
+
+/// 按键名比较两个条目，只在一边出现的键也算变化
+fn diff_keys(old : &BTreeMap<String, String>, new : &BTreeMap<String, String>) -> Vec<(String, String, String)>{
+    let mut changes = Vec::new();
+    let none = String::from("-");
+    for key in old.keys().chain(new.keys().filter(|k| !old.contains_key(*k))){
+        let old_value = old.get(key).unwrap_or(&none);
+        let new_value = new.get(key).unwrap_or(&none);
+        if old_value!=new_value{
+            changes.push((key.clone(), old_value.clone(), new_value.clone()));
+        }
+    }
+    changes
+}
+
+/// 在错误信息前加上文件名，否则只看得到 "No such file or directory"
+pub fn with_path(path : &str, e : io::Error) -> io::Error{
+    io::Error::new(e.kind(), format!("{}: {}",path,e))
+}
+
+/// 把空白、'\'、'='、'#'以及不可打印的字节写成'\'加三位八进制，这样条目不会被当成'#'开头的注释
+pub fn escape(s : &str) -> String{
+    let mut res = String::with_capacity(s.len());
+    for &b in s.as_bytes(){
+        if b.is_ascii_graphic() && b!=b'\\' && b!=b'=' && b!=b'#'{
+            res.push(b as char);
+        }
+        else{
+            res.push_str(&format!("\\{:03o}",b));
+        }
+    }
+    res
+}
+
+/// escape的逆操作，不合法的转义原样保留
+pub fn unescape(s : &str) -> String{
+    let bytes = s.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i<bytes.len(){
+        if bytes[i]==b'\\' && i+3<bytes.len() && bytes[i+1..i+4].iter().all(|c| (b'0'..=b'7').contains(c)){
+            let value = (bytes[i+1]-b'0') as u32*64 + (bytes[i+2]-b'0') as u32*8 + (bytes[i+3]-b'0') as u32;
+            res.push(value as u8);
+            i += 4;
+        }
+        else{
+            res.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::kstat::OptionSelected;
+    use std::fs;
+
+    #[test]
+    fn escape_special_bytes(){
+        assert_eq!(escape("plain/name.txt"), "plain/name.txt");
+        assert_eq!(escape("a b"), "a\\040b");
+        assert_eq!(escape("#x=\\"), "\\043x\\075\\134");
+        assert_eq!(escape("tab\t"), "tab\\011");
+    }
+
+    #[test]
+    fn unescape_round_trip(){
+        for name in ["a b", "#comment", "k=v", "back\\slash", "新 文件", "\\12x"]{
+            assert_eq!(unescape(&escape(name)), name);
+        }
+        // 不合法的转义原样保留
+        assert_eq!(unescape("\\9x"), "\\9x");
+    }
+
+    /// 用 --verify 跑一遍，返回退出码和报告
+    fn run_verify(manifest : &str, report : &str) -> (i32, String){
+        let mut opt = OptionSelected::default();
+        let args = ["rstat", "--verify", manifest, "-o", report].iter().map(|s| s.to_string()).collect();
+        opt.readoption(args).unwrap();
+        let code = opt.output().unwrap();
+        (code, fs::read_to_string(report).unwrap())
+    }
+
+    #[test]
+    fn snapshot_then_verify(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-manifest", std::process::id()));
+        let root = dir.join("tree").to_string_lossy().into_owned();
+        let later = dir.join("later").to_string_lossy().into_owned();
+        let manifest = dir.join("manifest").to_string_lossy().into_owned();
+        let report = dir.join("report").to_string_lossy().into_owned();
+        fs::create_dir_all(&root).unwrap();
+        fs::write(format!("{}/keep", root), b"same").unwrap();
+        fs::write(format!("{}/change", root), b"x").unwrap();
+        fs::write(format!("{}/gone", root), b"bye").unwrap();
+
+        let mut file = File::create(&manifest).unwrap();
+        snapshot(&mut file, &[root.clone(), later.clone()], &[Algorithm::Sha256]).unwrap();
+        drop(file);
+        // 什么都没改时没有任何报告，退出码为0
+        let (code, text) = run_verify(&manifest, &report);
+        assert_eq!((code, text.as_str()), (0, ""));
+
+        fs::write(format!("{}/change", root), b"xyz").unwrap();
+        fs::remove_file(format!("{}/gone", root)).unwrap();
+        fs::write(format!("{}/new", root), b"new").unwrap();
+        // 快照时不存在的根，现在出现了
+        fs::write(&later, b"").unwrap();
+
+        let (code, text) = run_verify(&manifest, &report);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(code, 1);
+        let lines : Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&format!("changed: {}/change: size: 1 -> 3", root).as_str()), "{}", text);
+        assert!(lines.iter().any(|l| l.starts_with(&format!("changed: {}/change: sha256: ", root))), "{}", text);
+        assert!(lines.contains(&format!("added: {}/new", root).as_str()), "{}", text);
+        assert!(lines.contains(&format!("removed: {}/gone", root).as_str()), "{}", text);
+        assert!(lines.contains(&format!("added: {}", later).as_str()), "{}", text);
+        assert!(!text.contains("/keep"), "{}", text);
+        assert!(!text.contains(&format!("changed: {}:", later)), "{}", text);
+    }
+}
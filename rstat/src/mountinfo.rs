@@ -0,0 +1,223 @@
+//! 读取 /proc/self/mountinfo，找出文件所在的挂载点、来源设备和挂载选项
+//!
+//! 每行的格式见 proc(5)：
+//!
+//! ```text
+//! 36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue
+//! (1)(2)(3)   (4)   (5)      (6)      (7)   (8) (9)   (10)         (11)
+//! ```
+//!
+//! 路径中的空白和'\'写成'\'加三位八进制
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::kstat::cpath;
+
+const MOUNTINFO : &str = "/proc/self/mountinfo";
+
+/// mountinfo中的一行
+#[derive(Debug, Clone)]
+pub struct Mount{
+    pub major : u32,
+    pub minor : u32,
+    pub root : String,          //挂载的是文件系统中的哪个目录，bind mount时不是"/"
+    pub mount_point : String,
+    pub mount_options : String, //每个挂载点自己的选项，例如 rw,nosuid,relatime
+    pub fs_type : String,
+    pub source : String,
+    pub super_options : String, //文件系统（超级块）的选项，同一文件系统的所有挂载点共用
+}
+
+impl Mount{
+    /// 合并挂载点和超级块的选项，重复的只留一个
+    /// 读写以挂载点的为准：只读挂载的可写文件系统，超级块的选项里仍然是rw
+    pub fn options(&self) -> String{
+        let mut options : Vec<&str> = self.mount_options.split(',').filter(|o| !o.is_empty()).collect();
+        for option in self.super_options.split(','){
+            if option.is_empty() || option=="rw" || option=="ro" || options.contains(&option){
+                continue;
+            }
+            options.push(option);
+        }
+        options.join(",")
+    }
+}
+
+/// statvfs的f_flag中的各位和名字，名字和mount(8)的选项一致
+const MOUNT_FLAGS : [(libc::c_ulong, &str); 11] = [
+    (libc::ST_NOSUID, "nosuid"),
+    (libc::ST_NODEV, "nodev"),
+    (libc::ST_NOEXEC, "noexec"),
+    (libc::ST_SYNCHRONOUS, "sync"),
+    (libc::ST_MANDLOCK, "mand"),
+    (libc::ST_WRITE, "write"),
+    (libc::ST_APPEND, "append"),
+    (libc::ST_IMMUTABLE, "immutable"),
+    (libc::ST_NOATIME, "noatime"),
+    (libc::ST_NODIRATIME, "nodiratime"),
+    (libc::ST_RELATIME, "relatime"),
+];
+
+/// 文件所在文件系统的挂载标志，逗号分隔，第一项总是rw或者ro
+/// libc中statfs的f_flags不公开，这里用statvfs，二者的值是一样的
+pub fn mount_flags(path : &str) -> io::Result<String>{
+    let mut buf : libc::statvfs = unsafe{ std::mem::zeroed() };
+    if unsafe{ libc::statvfs(cpath(path).as_ptr(), &mut buf) }==-1{
+        return Err(io::Error::last_os_error());
+    }
+    Ok(decode_flags(buf.f_flag))
+}
+
+fn decode_flags(flags : libc::c_ulong) -> String{
+    let mut names = vec![if flags & libc::ST_RDONLY!=0 { "ro" } else { "rw" }];
+    names.extend(MOUNT_FLAGS.iter().filter(|&&(bit, _)| flags & bit!=0).map(|&(_, name)| name));
+    names.join(",")
+}
+
+/// 读出当前所有的挂载，按文件中的顺序，后挂载的在后面
+pub fn mounts() -> io::Result<Vec<Mount>>{
+    Ok(fs::read_to_string(MOUNTINFO)?.lines().filter_map(parse_line).collect())
+}
+
+fn parse_line(line : &str) -> Option<Mount>{
+    // 第7项开始是数量不定的可选字段，以单独的"-"结束
+    let (front, back) = line.split_once(" - ")?;
+    // 跳过挂载ID和父挂载ID
+    let mut front = front.split(' ').skip(2);
+    let (major, minor) = front.next()?.split_once(':')?;
+    let root = unescape(front.next()?);
+    let mount_point = unescape(front.next()?);
+    let mount_options = front.next()?.to_string();
+    let mut back = back.split(' ');
+    Some(Mount{
+        major : major.parse().ok()?,
+        minor : minor.parse().ok()?,
+        root,
+        mount_point,
+        mount_options,
+        fs_type : unescape(back.next()?),
+        source : unescape(back.next()?),
+        super_options : back.next().unwrap_or_default().to_string(),
+    })
+}
+
+/// --all-mounts 要列出的挂载点，按mountinfo中的顺序
+/// types不为空时只保留这些类型，exclude中的类型总是去掉；
+/// 被后来的挂载盖住的挂载点看不到，同一个文件系统的多次挂载（bind mount）只保留一个，
+/// 优先保留挂载了整个文件系统（root为"/"）的那一个
+pub fn mount_points(types : &[String], exclude : &[String]) -> io::Result<Vec<String>>{
+    Ok(select(&mounts()?, types, exclude).into_iter().map(|m| m.mount_point.clone()).collect())
+}
+
+/// 先在所有挂载中判断是否被盖住，再按类型过滤：
+/// 盖住它的挂载即使被过滤掉了，它本身也仍然是看不到的
+fn select<'a>(mounts : &'a [Mount], types : &[String], exclude : &[String]) -> Vec<&'a Mount>{
+    let visible = mounts.iter().enumerate()
+        .filter(|(i, mount)| !mounts[i+1..].iter().any(|m| m.mount_point==mount.mount_point))
+        .map(|(_, mount)| mount)
+        .filter(|m| types.is_empty() || types.contains(&m.fs_type))
+        .filter(|m| !exclude.contains(&m.fs_type));
+    let mut chosen : Vec<&Mount> = Vec::new();
+    for mount in visible{
+        match chosen.iter_mut().find(|c| c.major==mount.major && c.minor==mount.minor){
+            Some(c) => {
+                if c.root!="/" && mount.root=="/"{
+                    *c = mount;
+                }
+            },
+            None => chosen.push(mount),
+        }
+    }
+    chosen
+}
+
+/// 把'\'加三位八进制还原成原来的字节
+fn unescape(text : &str) -> String{
+    let bytes = text.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i<bytes.len(){
+        if bytes[i]==b'\\'{
+            if let Some(value) = text.get(i+1..i+4).and_then(|o| u8::from_str_radix(o, 8).ok()){
+                res.push(value);
+                i += 4;
+                continue;
+            }
+        }
+        res.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+/// 找出path所在的挂载
+/// 取挂载点是path的前缀且设备号相同的挂载中最长的一个，同一个挂载点被挂了多次时最后一次生效（max_by_key取最后一个）；
+/// btrfs子卷等stat给出的设备号和mountinfo不一致时只按路径判断
+pub fn find(path : &str) -> Option<Mount>{
+    let canonical = fs::canonicalize(path).ok()?;
+    let dev = fs::metadata(&canonical).ok()?.dev();
+    let mounts = mounts().ok()?;
+    let covering : Vec<&Mount> = mounts.iter().filter(|m| canonical.starts_with(Path::new(&m.mount_point))).collect();
+    let longest = |candidates : Vec<&Mount>|{
+        candidates.into_iter().max_by_key(|m| Path::new(&m.mount_point).components().count()).cloned()
+    };
+    let same_dev : Vec<&Mount> = covering.iter().copied()
+        .filter(|m| m.major==libc::major(dev) && m.minor==libc::minor(dev))
+        .collect();
+    if same_dev.is_empty(){
+        longest(covering)
+    }
+    else{
+        longest(same_dev)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn table(lines : &[&str]) -> Vec<Mount>{
+        lines.iter().map(|l| parse_line(l).unwrap()).collect()
+    }
+
+    fn select_points(mounts : &[Mount], types : &[&str], exclude : &[&str]) -> Vec<String>{
+        let types : Vec<String> = types.iter().map(|t| t.to_string()).collect();
+        let exclude : Vec<String> = exclude.iter().map(|t| t.to_string()).collect();
+        select(mounts, &types, &exclude).into_iter().map(|m| m.mount_point.clone()).collect()
+    }
+
+    #[test]
+    fn parse_escaped_line(){
+        let mount = parse_line(r"36 35 98:0 /mnt1 /mnt\040two rw,noatime master:1 - ext3 /dev/root rw,errors=continue").unwrap();
+        assert_eq!((mount.major, mount.minor), (98, 0));
+        assert_eq!(mount.root, "/mnt1");
+        assert_eq!(mount.mount_point, "/mnt two");
+        assert_eq!(mount.fs_type, "ext3");
+        assert_eq!(mount.options(), "rw,noatime,errors=continue");
+    }
+
+    /// 被盖住的ext4挂载不能因为盖住它的tmpfs被过滤掉而重新出现
+    #[test]
+    fn covered_mounts_stay_hidden_after_type_filter(){
+        let mounts = table(&[
+            "1 0 8:1 / / rw - ext4 /dev/sda1 rw",
+            "2 1 8:2 / /data rw - ext4 /dev/sda2 rw",
+            "3 1 0:30 / /data rw - tmpfs tmpfs rw",
+        ]);
+        assert_eq!(select_points(&mounts, &[], &[]), ["/", "/data"]);
+        assert_eq!(select_points(&mounts, &["ext4"], &[]), ["/"]);
+        assert_eq!(select_points(&mounts, &[], &["tmpfs"]), ["/"]);
+        assert_eq!(select_points(&mounts, &["tmpfs"], &[]), ["/data"]);
+    }
+
+    #[test]
+    fn bind_mounts_prefer_whole_filesystem(){
+        let mounts = table(&[
+            "1 0 8:1 /srv /srv rw - ext4 /dev/sda1 rw",
+            "2 0 8:1 / /mnt rw - ext4 /dev/sda1 rw",
+        ]);
+        assert_eq!(select_points(&mounts, &[], &[]), ["/mnt"]);
+    }
+}
@@ -0,0 +1,401 @@
+//! BSD mtree(5) 格式的规格说明：根据FileStat生成，或者按已有的规格检查一棵目录树
+//!
+//! 生成的格式和 `mtree -c` 相同：每个目录前用 /set 给出该目录下文件最常见的属性，
+//! 和默认值相同的关键字不再重复，目录内容结束时写一行 ".."
+//! 检查时既接受这种层级格式，也接受每行一个完整路径（./a/b）的格式
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+use chrono::prelude::*;
+
+use crate::digest::{self, Algorithm};
+use crate::kstat::{get_file_stat, get_groupname_with_id, get_username_with_id, FilePermission, FileStat, StatError};
+use crate::manifest::{escape, type_keyword, unescape, walk, with_path};
+
+/// mtree发现不一致时的退出码，和 --compare、--verify 一样是1，2留给出错
+pub const MISMATCH_EXIT : i32 = 1;
+
+/// /set 中给出的默认值
+#[derive(PartialEq, Eq, Hash, Clone)]
+struct SetDefaults{
+    uid : u32,
+    gname : String,
+    mode : String,
+}
+
+/// 为root生成规格说明
+pub fn emit<W : Write>(out : &mut W, root : &str, digests : &[Algorithm]) -> io::Result<()>{
+    let tree = std::fs::canonicalize(root).map(|p| p.to_string_lossy().into_owned()).unwrap_or_else(|_| root.to_string());
+    writeln!(out, "#{:>14} {}","user:",get_username_with_id(unsafe{ libc::geteuid() }))?;
+    writeln!(out, "#{:>14} {}","machine:",hostname())?;
+    writeln!(out, "#{:>14} {}","tree:",tree)?;
+    writeln!(out, "#{:>14} {}","date:",Local::now().format("%a %b %e %H:%M:%S %Y"))?;
+    writeln!(out)?;
+
+    let filestat = get_file_stat(root.to_string());
+    if !filestat.alive{
+        return Err(io::Error::other(format!("{}: {}",root,filestat.err_info)));
+    }
+    let mut set = None;
+    if is_dir(&filestat){
+        emit_dir(out, root, ".", &filestat, 0, &mut set, digests)
+    }
+    else{
+        // 单个文件没有目录结构，直接写出所有关键字
+        writeln!(out, "{}",entry_line(".", &filestat, None, digests))
+    }
+}
+
+/// root为整棵树的根，注释中的目录路径相对它来写
+fn emit_dir<W : Write>(out : &mut W, root : &str, name : &str, dirstat : &FileStat, depth : usize,
+                       set : &mut Option<SetDefaults>, digests : &[Algorithm]) -> io::Result<()>{
+    let path = &dirstat.filename;
+    let indent = " ".repeat(depth*4);
+    let mut children : Vec<(String, FileStat)> = match std::fs::read_dir(path){
+        Ok(dir) => dir.filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().into_owned())
+            .map(|n|{
+                let child = get_file_stat(format!("{}/{}",path.trim_end_matches('/'),n));
+                (n, child)
+            })
+            .filter(|(_, child)| child.alive)
+            .collect(),
+        Err(_) => vec![],
+    };
+    children.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // 和mtree一样，用这个目录下最常见的属性作为 /set 的默认值
+    let mut counts : HashMap<SetDefaults, usize> = HashMap::new();
+    for (_, child) in children.iter().filter(|(_, c)| !is_dir(c)){
+        *counts.entry(defaults_of(child)).or_insert(0) += 1;
+    }
+    if let Some((common, _)) = counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.mode.cmp(&a.0.mode))){
+        if set.as_ref()!=Some(&common){
+            writeln!(out, "/set type=file uid={} gname={} mode={}",common.uid,escape(&common.gname),common.mode)?;
+            *set = Some(common);
+        }
+    }
+
+    writeln!(out, "{}{}",indent,entry_line(name, dirstat, None, digests))?;
+    for (child_name, child) in children.iter().filter(|(_, c)| !is_dir(c)){
+        writeln!(out, "{}    {}",indent,entry_line(child_name, child, set.as_ref(), digests))?;
+    }
+    for (child_name, child) in children.iter().filter(|(_, c)| is_dir(c)){
+        writeln!(out)?;
+        writeln!(out, "# {}",escape(&relative_path(root, &child.filename)))?;
+        emit_dir(out, root, child_name, child, depth+1, set, digests)?;
+    }
+    writeln!(out, "{}..",indent)?;
+    if depth>0{
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// 一个条目的名字和关键字，和set中相同的关键字省略
+fn entry_line(name : &str, filestat : &FileStat, set : Option<&SetDefaults>, digests : &[Algorithm]) -> String{
+    let st = &filestat.stat;
+    let defaults = defaults_of(filestat);
+    let ftype = type_keyword(st.st_mode);
+    let mut line = escape(name);
+    if set.is_none() || ftype!="file"{
+        line.push_str(&format!(" type={}",ftype));
+    }
+    if set.map(|s| s.uid)!=Some(defaults.uid){
+        line.push_str(&format!(" uid={}",defaults.uid));
+    }
+    if set.map(|s| &s.gname)!=Some(&defaults.gname){
+        line.push_str(&format!(" gname={}",escape(&defaults.gname)));
+    }
+    if set.map(|s| &s.mode)!=Some(&defaults.mode){
+        line.push_str(&format!(" mode={}",defaults.mode));
+    }
+    if !is_dir(filestat){
+        line.push_str(&format!(" size={}",st.st_size));
+    }
+    line.push_str(&format!(" time={}.{:09}",st.st_mtime,st.st_mtime_nsec));
+    if filestat.blinker{
+        line.push_str(&format!(" link={}",escape(&filestat.oriname)));
+    }
+    // mtree没有对应关键字的算法不写
+    let digests : Vec<Algorithm> = digests.iter().copied().filter(|a| a.mtree_keyword().is_some()).collect();
+    if !digests.is_empty() && ftype=="file"{
+        if let Ok(sums) = digest::file_digests(&filestat.filename, &digests){
+            for (algorithm, sum) in digests.iter().zip(sums){
+                line.push_str(&format!(" {}={}",algorithm.mtree_keyword().unwrap_or_default(),sum));
+            }
+        }
+    }
+    line
+}
+
+fn defaults_of(filestat : &FileStat) -> SetDefaults{
+    let st = &filestat.stat;
+    SetDefaults{
+        uid : st.st_uid,
+        gname : get_groupname_with_id(st.st_gid),
+        mode : FilePermission::new(st.st_mode).output_num(),
+    }
+}
+
+fn is_dir(filestat : &FileStat) -> bool{
+    filestat.stat.st_mode & libc::S_IFMT==libc::S_IFDIR
+}
+
+fn hostname() -> String{
+    let mut buf = [0u8; 256];
+    let ret = unsafe{ libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if ret==-1{
+        return String::from("-");
+    }
+    let end = buf.iter().position(|&b| b==0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..end]).into_owned()
+}
+
+/// 按spec检查root，用mtree自己的格式报告不一致的地方
+/// 返回是否完全一致
+pub fn check<W : Write>(out : &mut W, spec : &str, root : &str) -> Result<bool,StatError>{
+    let file = File::open(spec).map_err(|e| with_path(spec, e))?;
+    let mut expected = parse_spec(BufReader::new(file))?;
+    let mut clean = true;
+
+    walk(root, &mut |filestat|{
+        let rel = relative_path(root, &filestat.filename);
+        match expected.remove(&rel){
+            None => {
+                clean = false;
+                writeln!(out, "extra: {}",rel)
+            },
+            Some(keys) => {
+                let changes = compare_keys(&keys, &filestat);
+                if !changes.is_empty(){
+                    clean = false;
+                    writeln!(out, "{} changed",rel)?;
+                    for line in changes{
+                        writeln!(out, "\t{}",line)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    })?;
+
+    // 按路径排序，保证同样的输入总是同样的输出
+    let mut missing : Vec<String> = expected.into_keys().collect();
+    missing.sort();
+    for path in missing{
+        clean = false;
+        writeln!(out, "{} missing",path)?;
+    }
+    Ok(clean)
+}
+
+/// walk得到的path相对root的路径，和规格说明一样写成"."或"./a/b"
+/// root可以是"/"或带结尾的'/'，这时walk给出的root本身是"/"或"dir/"
+fn relative_path(root : &str, path : &str) -> String{
+    let rest = path[root.trim_end_matches('/').len()..].trim_start_matches('/');
+    if rest.is_empty(){
+        ".".to_string()
+    }else{
+        format!("./{}",rest)
+    }
+}
+
+/// 解析规格说明，得到 路径 -> 关键字 的表，路径都以"./"开头
+fn parse_spec<R : BufRead>(reader : R) -> Result<HashMap<String, HashMap<String, String>>,StatError>{
+    let mut entries = HashMap::new();
+    let mut set : HashMap<String, String> = HashMap::new();
+    let mut cwd : Vec<String> = Vec::new();
+
+    for line in reader.lines(){
+        let line = line?;
+        let mut words = line.split_whitespace();
+        let Some(first) = words.next() else{
+            continue;
+        };
+        if first.starts_with('#'){
+            continue;
+        }
+        match first{
+            "/set" => {
+                for word in words{
+                    let (key, value) = word.split_once('=').ok_or(StatError::InvalidManifest)?;
+                    set.insert(key.to_string(), unescape(value));
+                }
+            },
+            "/unset" => {
+                for word in words{
+                    if word=="all"{
+                        set.clear();
+                    }
+                    set.remove(word);
+                }
+            },
+            ".." => {
+                cwd.pop();
+            },
+            _ => {
+                let name = unescape(first);
+                let mut keys = set.clone();
+                for word in words{
+                    let (key, value) = word.split_once('=').ok_or(StatError::InvalidManifest)?;
+                    keys.insert(key.to_string(), unescape(value));
+                }
+                let path = if name.contains('/'){
+                    // 完整路径格式，不影响当前目录
+                    if name.starts_with("./") { name } else { format!("./{}",name) }
+                }
+                else{
+                    let path = if cwd.is_empty() && name=="." { name.clone() } else { format!("{}/{}",cwd.join("/"),name) };
+                    if keys.get("type").map(|t| t.as_str())==Some("dir"){
+                        cwd.push(name);
+                    }
+                    path
+                };
+                entries.insert(path, keys);
+            },
+        }
+    }
+    Ok(entries)
+}
+
+/// 比较规格中给出的关键字，文字和mtree的输出保持一致
+fn compare_keys(keys : &HashMap<String, String>, filestat : &FileStat) -> Vec<String>{
+    let st = &filestat.stat;
+    let mut changes = Vec::new();
+    let mut check = |name : &str, expected : &str, found : String|{
+        if expected!=found{
+            changes.push(format!("{} expected {} found {}",name,expected,found));
+        }
+    };
+
+    if let Some(v) = keys.get("type"){
+        check("type", v, type_keyword(st.st_mode).to_string());
+    }
+    if let Some(v) = keys.get("uid"){
+        check("user", v, st.st_uid.to_string());
+    }
+    if let Some(v) = keys.get("uname"){
+        check("user", v, get_username_with_id(st.st_uid));
+    }
+    if let Some(v) = keys.get("gid"){
+        check("gid", v, st.st_gid.to_string());
+    }
+    if let Some(v) = keys.get("gname"){
+        check("gid", v, get_groupname_with_id(st.st_gid));
+    }
+    if let Some(v) = keys.get("mode"){
+        // 规格中的mode可能不带前导0
+        let expected = u32::from_str_radix(v, 8).map(|m| format!("{:04o}",m)).unwrap_or_else(|_| v.clone());
+        check("permissions", &expected, format!("{:04o}",st.st_mode & 0o7777));
+    }
+    if let Some(v) = keys.get("nlink"){
+        check("link_count", v, st.st_nlink.to_string());
+    }
+    // 目录的大小和文件系统有关，mtree同样不比较
+    if let Some(v) = keys.get("size").filter(|_| !is_dir(filestat)){
+        check("size", v, st.st_size.to_string());
+    }
+    if let Some(v) = keys.get("time"){
+        let (sec, nsec) = v.split_once('.').unwrap_or((v, "0"));
+        let expected = (sec.parse::<i64>().unwrap_or(0), nsec.parse::<i64>().unwrap_or(0));
+        let found = (st.st_mtime, st.st_mtime_nsec);
+        if expected!=found{
+            // 只有纳秒不同时日期的写法一样，这时直接给出原始数值
+            let (expected, found) = if expected.0==found.0{
+                (format!("{}.{:09}",expected.0,expected.1), format!("{}.{:09}",found.0,found.1))
+            }
+            else{
+                (mtree_time(expected.0), mtree_time(found.0))
+            };
+            check("modification time", &expected, found);
+        }
+    }
+    if let Some(v) = keys.get("link"){
+        check("link_ref", v, filestat.oriname.clone());
+    }
+    // 摘要关键字也可以简写为算法名，例如 sha256
+    for algorithm in Algorithm::ALL{
+        let Some(keyword) = algorithm.mtree_keyword() else{
+            continue;
+        };
+        if let Some(v) = keys.get(keyword).or_else(|| keys.get(algorithm.name())){
+            let found = digest::file_digest(&filestat.filename, algorithm).unwrap_or_else(|e| e.to_string());
+            check(keyword, v, found);
+        }
+    }
+    changes
+}
+
+fn mtree_time(sec : i64) -> String{
+    match Local.timestamp_opt(sec, 0){
+        chrono::LocalResult::Single(t) => t.format("%a %b %e %H:%M:%S %Y").to_string(),
+        _ => sec.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn relative_path_of_root(){
+        assert_eq!(relative_path("/", "/"), ".");
+        assert_eq!(relative_path("dir", "dir"), ".");
+        assert_eq!(relative_path("dir/", "dir/"), ".");
+        assert_eq!(relative_path("dir//", "dir//"), ".");
+    }
+
+    #[test]
+    fn relative_path_of_children(){
+        assert_eq!(relative_path("/", "/etc"), "./etc");
+        assert_eq!(relative_path("/", "/etc/hosts"), "./etc/hosts");
+        assert_eq!(relative_path("dir", "dir/a/b"), "./a/b");
+        assert_eq!(relative_path("dir/", "dir/a"), "./a");
+    }
+
+    #[test]
+    fn check_missing_spec_names_the_path(){
+        let mut out = Vec::new();
+        let err = check(&mut out, "/nonexistent/rstat.spec", ".").err().unwrap();
+        assert!(err.to_string().contains("/nonexistent/rstat.spec"));
+    }
+
+    #[test]
+    fn emit_then_check(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-mtree", std::process::id()));
+        let root = dir.join("tree").to_string_lossy().into_owned();
+        let spec = dir.join("spec").to_string_lossy().into_owned();
+        std::fs::create_dir_all(format!("{}/sub/deeper", root)).unwrap();
+        std::fs::write(format!("{}/top", root), b"top").unwrap();
+        std::fs::write(format!("{}/sub/file", root), b"file").unwrap();
+        std::fs::write(format!("{}/sub/gone", root), b"gone").unwrap();
+
+        let mut text = Vec::new();
+        emit(&mut text, &root, &[Algorithm::Sha256]).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        std::fs::write(&spec, &text).unwrap();
+        // 子目录的注释相对根来写，不带临时目录的绝对路径
+        assert!(text.lines().any(|l| l=="# ./sub"), "{}", text);
+        assert!(text.lines().any(|l| l=="# ./sub/deeper"), "{}", text);
+        assert!(!text.contains(&format!("# {}", root)), "{}", text);
+
+        let mut out = Vec::new();
+        assert!(check(&mut out, &spec, &root).unwrap());
+        assert_eq!(String::from_utf8_lossy(&out), "");
+
+        std::fs::write(format!("{}/sub/file", root), b"changed").unwrap();
+        std::fs::remove_file(format!("{}/sub/gone", root)).unwrap();
+        std::fs::write(format!("{}/sub/new", root), b"new").unwrap();
+        let mut out = Vec::new();
+        let clean = check(&mut out, &spec, &root).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let out = String::from_utf8(out).unwrap();
+        assert!(!clean);
+        assert!(out.contains("./sub/file changed\n\tsize expected 4 found 7\n"), "{}", out);
+        assert!(out.lines().any(|l| l=="extra: ./sub/new"), "{}", out);
+        assert!(out.lines().any(|l| l=="./sub/gone missing"), "{}", out);
+        assert!(!out.contains("./top"), "{}", out);
+    }
+}
@@ -0,0 +1,168 @@
+//! 使用线程池并行获取文件信息
+//! 在网络文件系统上 stat/statfs/readlink 的延迟很高，顺序调用时总耗时和文件数成正比，
+//! 这里把调用分给多个线程，再按输入顺序一个个交给输出端
+//! stat 不会阻塞时（本地文件系统、热缓存）线程切换的开销比省下的时间还多，所以 --jobs 默认是 1，
+//! 见 benches/jobs.rs 里的测量结果
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// 每个线程最多可以领先输出端多少个结果，用来限制乱序结果占用的内存
+const WINDOW_PER_JOB : usize = 4;
+
+/// 各个线程共享的状态
+struct Shared<F>{
+    files : Vec<String>,
+    getter : F,
+    next : AtomicUsize,         //下一个要领取的下标
+    permits : Mutex<usize>,     //还可以领取多少个，输出端每取走一个就还回一个
+    cond : Condvar,
+    stop : AtomicBool,          //输出端已经不要结果了
+}
+
+impl<F> Shared<F>{
+    /// 领取一个名额，输出端提前结束时返回false
+    fn acquire(&self) -> bool{
+        let mut permits = self.permits.lock().unwrap();
+        while *permits==0 && !self.stop.load(Ordering::Relaxed){
+            permits = self.cond.wait(permits).unwrap();
+        }
+        if self.stop.load(Ordering::Relaxed){
+            return false;
+        }
+        *permits -= 1;
+        true
+    }
+
+    fn release(&self){
+        *self.permits.lock().unwrap() += 1;
+        self.cond.notify_one();
+    }
+}
+
+/// 按输入顺序给出结果的迭代器，结果一边获取一边交出，不需要等全部文件处理完
+pub struct OrderedIter<T, F>{
+    shared : Arc<Shared<F>>,
+    rx : mpsc::Receiver<(usize, T)>,
+    pending : HashMap<usize, T>,    //已经拿到但还没轮到输出的结果
+    index : usize,                  //下一个要交出的下标
+    workers : Vec<JoinHandle<()>>,
+}
+
+impl<T, F> OrderedIter<T, F>
+where
+    T: Send + 'static,
+    F: Fn(String) -> T + Send + Sync + 'static,
+{
+    /// 用 jobs 个线程对 files 中的每一项调用 getter
+    pub fn new(files : Vec<String>, jobs : usize, getter : F) -> Self{
+        let jobs = jobs.clamp(1, files.len().max(1));
+        let shared = Arc::new(Shared{
+            files,
+            getter,
+            next : AtomicUsize::new(0),
+            permits : Mutex::new(jobs*WINDOW_PER_JOB),
+            cond : Condvar::new(),
+            stop : AtomicBool::new(false),
+        });
+        let (tx, rx) = mpsc::channel();
+
+        let workers = (0..jobs).map(|_|{
+            let shared = Arc::clone(&shared);
+            let tx = tx.clone();
+            // 每个线程不断领取下一个还没处理的下标，直到全部领完
+            thread::spawn(move ||{
+                while shared.acquire(){
+                    let index = shared.next.fetch_add(1, Ordering::Relaxed);
+                    let Some(name) = shared.files.get(index) else{
+                        break;
+                    };
+                    if tx.send((index, (shared.getter)(name.clone()))).is_err(){
+                        break;
+                    }
+                }
+            })
+        }).collect();
+
+        OrderedIter{
+            shared,
+            rx,
+            pending : HashMap::new(),
+            index : 0,
+            workers,
+        }
+    }
+}
+
+impl<T, F> Iterator for OrderedIter<T, F>{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T>{
+        if self.index>=self.shared.files.len(){
+            return None;
+        }
+        loop{
+            if let Some(value) = self.pending.remove(&self.index){
+                self.index += 1;
+                self.shared.release();
+                return Some(value);
+            }
+            match self.rx.recv(){
+                Ok((index, value)) => {
+                    self.pending.insert(index, value);
+                },
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl<T, F> Drop for OrderedIter<T, F>{
+    fn drop(&mut self){
+        // 让还在等名额的线程退出
+        {
+            let _permits = self.shared.permits.lock().unwrap();
+            self.shared.stop.store(true, Ordering::Relaxed);
+        }
+        self.shared.cond.notify_all();
+        for worker in self.workers.drain(..){
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn ordered_iter_keeps_input_order(){
+        // 越靠前的文件越慢，保证后面的结果先完成
+        let files : Vec<String> = (0..16).map(|i| i.to_string()).collect();
+        let finished = Arc::new(Mutex::new(Vec::new()));
+        let done = Arc::clone(&finished);
+        let getter = move |name : String|{
+            let index : u64 = name.parse().unwrap();
+            thread::sleep(Duration::from_millis((16-index)*5));
+            done.lock().unwrap().push(index);
+            index
+        };
+        let result : Vec<u64> = OrderedIter::new(files, 4, getter).collect();
+        assert_eq!(result, (0..16).collect::<Vec<u64>>());
+
+        let finished = finished.lock().unwrap();
+        assert_eq!(finished.len(), 16);
+        assert!(finished.windows(2).any(|w| w[0]>w[1]));
+    }
+
+    #[test]
+    fn ordered_iter_stops_early(){
+        let files : Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let mut iter = OrderedIter::new(files, 8, |name : String| name);
+        assert_eq!(iter.next().as_deref(), Some("0"));
+        assert_eq!(iter.next().as_deref(), Some("1"));
+        drop(iter);
+    }
+}
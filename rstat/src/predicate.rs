@@ -0,0 +1,643 @@
+//! 条件表达式：格式串中的条件段（%{?link: -> %{target}}、%{if type=dir}…%{else}…%{end}）和 --where 共用
+//!
+//! ```text
+//! 表达式  := 与 ( '||' 与 )*
+//! 与      := 非 ( '&&' 非 )*
+//! 非      := '!' 非 | '(' 表达式 ')' | 条件
+//! 条件    := 名字 | 字段 比较符 值
+//! 比较符  := = == != < <= > >=
+//! ```
+//!
+//! 单独的名字：
+//!
+//! ```text
+//! file dir link block char fifo socket    文件类型
+//! device                                  字符设备或块设备
+//! setuid setgid sticky                    特殊权限位
+//! exec                                    任何人有执行权限
+//! empty                                   大小为0
+//! ```
+//!
+//! 字段：
+//!
+//! ```text
+//! type                类型名，或者 find -type 的字母，只能用 = 和 !=
+//! name path           文件名的最后一部分、完整的文件名，值是可以带 * 和 ? 的通配符，只能用 = 和 !=
+//! user group          名字或者ID，只能用 = 和 !=
+//! uid gid links inode 数字
+//! size                字节数，可以带 K M G T P 后缀（按1024换算），例如 1.5M
+//! mode perm           八进制的权限，例如 0644
+//! atime mtime ctime   带单位 s m h d w y 的时长表示相对于 --now 的时间，-7d 和 7d 都是7天前，+1h 是1小时后；
+//!                     不带单位时按 --now 的写法给出时刻，例如 2024-01-01、@1700000000
+//! ```
+//!
+//! 值中有空白或者 ()&|!=<> 时用单引号或者双引号括起来
+//! 不带 -L 时符号链接本身的类型是link，带 -L 时为指向的文件的类型
+use libc::{S_IFBLK, S_IFCHR, S_IFDIR, S_IFIFO, S_IFLNK, S_IFMT, S_IFREG, S_IFSOCK};
+
+use crate::kstat::{lookup_groupname, lookup_username, FileStat, StatError};
+use crate::style::parse_timestamp;
+
+/// 解析后的条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate{
+    Type(u32),      //st_mode中S_IFMT部分的值
+    Device,
+    Setuid,
+    Setgid,
+    Sticky,
+    Exec,
+    Empty,
+    Compare(Field, Op, Operand),
+    Name(bool, String),     //true为完整的文件名
+    User(String),
+    Group(String),
+    Not(Box<Predicate>),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+/// 可以比较大小的字段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field{
+    Uid,
+    Gid,
+    Links,
+    Inode,
+    Size,
+    Mode,
+    Atime,
+    Mtime,
+    Ctime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op{
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// 比较的值，Ago为相对于"现在"往前的秒数，匹配时才换算成时刻
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand{
+    Number(i64),
+    Ago(i64),
+}
+
+impl Predicate{
+    /// 解析失败时给出出错的位置（从1开始的字符数）和原因
+    pub fn parse(text : &str) -> Result<Predicate, StatError>{
+        let tokens = tokenize(text)?;
+        let mut parser = Parser{ tokens : &tokens, pos : 0, len : text.chars().count() };
+        let predicate = parser.or()?;
+        match parser.peek(){
+            Some((Token::RParen, column)) => Err(expression_error(*column, "unmatched ')'")),
+            Some((token, column)) => Err(expression_error(*column, &format!("unexpected '{}', expected '&&' or '||'", token.text()))),
+            None => Ok(predicate),
+        }
+    }
+
+    /// 解析 %{?条件:文字} 中的条件，返回条件和分隔用的':'的位置（字节下标）
+    /// 按拆记号的规则跳过引号中的':'；时间里也有':'，所以取第一个使前面的部分成为完整条件的':'，
+    /// 例如 mtime>2024-01-01T10:00:00:文字 在最后一个':'处分开；引号外的'}'之后不再找
+    pub fn parse_prefix(text : &str) -> Result<(Predicate, usize), StatError>{
+        let mut error = StatError::InvalidFormat;
+        let mut chars = text.char_indices();
+        let mut token_start = true;
+        while let Some((i, c)) = chars.next(){
+            match c{
+                // 和tokenize一样，引号只在记号的开头才起作用
+                '\'' | '"' if token_start => {
+                    if !chars.by_ref().any(|(_, q)| q==c){
+                        break;
+                    }
+                    continue;
+                },
+                '}' => break,
+                ':' => match Predicate::parse(&text[..i]){
+                    Ok(predicate) => return Ok((predicate, i)),
+                    Err(e) => error = e,
+                },
+                _ => {},
+            }
+            token_start = is_special(c);
+        }
+        Err(error)
+    }
+
+    /// now为换算相对时间时的"现在"
+    pub fn matches(&self, iter : &FileStat, now : i64) -> bool{
+        let stat = &iter.stat;
+        let mode = stat.st_mode;
+        match self{
+            Predicate::Type(bits) => mode & S_IFMT==*bits,
+            Predicate::Device => matches!(mode & S_IFMT, S_IFBLK | S_IFCHR),
+            Predicate::Setuid => mode & libc::S_ISUID!=0,
+            Predicate::Setgid => mode & libc::S_ISGID!=0,
+            Predicate::Sticky => mode & libc::S_ISVTX!=0,
+            Predicate::Exec => mode & 0o111!=0,
+            Predicate::Empty => stat.st_size==0,
+            Predicate::Compare(field, op, operand) => {
+                let value = match field{
+                    Field::Uid => stat.st_uid as i64,
+                    Field::Gid => stat.st_gid as i64,
+                    Field::Links => stat.st_nlink as i64,
+                    Field::Inode => stat.st_ino as i64,
+                    Field::Size => stat.st_size,
+                    Field::Mode => (mode & 0o7777) as i64,
+                    Field::Atime => stat.st_atime,
+                    Field::Mtime => stat.st_mtime,
+                    Field::Ctime => stat.st_ctime,
+                };
+                let operand = match operand{
+                    Operand::Number(n) => *n,
+                    Operand::Ago(n) => now.saturating_sub(*n),
+                };
+                match op{
+                    Op::Eq => value==operand,
+                    Op::Ne => value!=operand,
+                    Op::Lt => value<operand,
+                    Op::Le => value<=operand,
+                    Op::Gt => value>operand,
+                    Op::Ge => value>=operand,
+                }
+            },
+            Predicate::Name(full, pattern) => {
+                let name = if *full { iter.filename.as_str() } else { base_name(&iter.filename) };
+                glob_match(pattern, name)
+            },
+            Predicate::User(user) => match user.parse::<u32>(){
+                Ok(uid) => stat.st_uid==uid,
+                Err(_) => lookup_username(stat.st_uid).as_deref()==Some(user.as_str()),
+            },
+            Predicate::Group(group) => match group.parse::<u32>(){
+                Ok(gid) => stat.st_gid==gid,
+                Err(_) => lookup_groupname(stat.st_gid).as_deref()==Some(group.as_str()),
+            },
+            Predicate::Not(inner) => !inner.matches(iter, now),
+            Predicate::And(a, b) => a.matches(iter, now) && b.matches(iter, now),
+            Predicate::Or(a, b) => a.matches(iter, now) || b.matches(iter, now),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token{
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+impl Token{
+    /// 错误信息中记号的写法
+    fn text(&self) -> String{
+        match self{
+            Token::Word(word) => word.clone(),
+            Token::Op(op) => op.symbol().to_string(),
+            Token::And => String::from("&&"),
+            Token::Or => String::from("||"),
+            Token::Not => String::from("!"),
+            Token::LParen => String::from("("),
+            Token::RParen => String::from(")"),
+        }
+    }
+}
+
+impl Op{
+    fn symbol(&self) -> &'static str{
+        match self{
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+/// 不能出现在没有引号的值中的字符
+fn is_special(c : char) -> bool{
+    c.is_whitespace() || "()&|!=<>".contains(c)
+}
+
+/// 拆成记号，每个记号带上开始的位置
+fn tokenize(text : &str) -> Result<Vec<(Token, usize)>, StatError>{
+    let chars : Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i<chars.len(){
+        let column = i+1;
+        let next = chars.get(i+1).copied();
+        let (token, width) = match (chars[i], next){
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            },
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('!', _) => (Token::Not, 1),
+            ('=', _) => (Token::Op(Op::Eq), 1),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('&', _) | ('|', _) => return Err(expression_error(column, &format!("'{}' must be doubled", chars[i]))),
+            (quote @ ('\'' | '"'), _) => {
+                let Some(len) = chars[i+1..].iter().position(|&c| c==quote) else{
+                    return Err(expression_error(column, "unterminated quote"));
+                };
+                (Token::Word(chars[i+1..i+1+len].iter().collect()), len+2)
+            },
+            _ => {
+                let len = chars[i..].iter().position(|&c| is_special(c)).unwrap_or(chars.len()-i);
+                (Token::Word(chars[i..i+len].iter().collect()), len)
+            },
+        };
+        tokens.push((token, column));
+        i += width;
+    }
+    Ok(tokens)
+}
+
+fn expression_error(column : usize, reason : &str) -> StatError{
+    StatError::InvalidExpression(format!("column {}: {}",column,reason))
+}
+
+struct Parser<'a>{
+    tokens : &'a [(Token, usize)],
+    pos : usize,
+    len : usize,        //表达式的字符数，用于给出结尾处的错误位置
+}
+
+impl Parser<'_>{
+    fn peek(&self) -> Option<&(Token, usize)>{
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)>{
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// 表达式结尾的位置
+    fn end(&self) -> usize{
+        self.len+1
+    }
+
+    fn or(&mut self) -> Result<Predicate, StatError>{
+        let mut left = self.and()?;
+        while let Some((Token::Or, _)) = self.peek(){
+            self.pos += 1;
+            left = Predicate::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Predicate, StatError>{
+        let mut left = self.unary()?;
+        while let Some((Token::And, _)) = self.peek(){
+            self.pos += 1;
+            left = Predicate::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Predicate, StatError>{
+        match self.next(){
+            Some((Token::Not, _)) => Ok(Predicate::Not(Box::new(self.unary()?))),
+            Some((Token::LParen, column)) => {
+                let inner = self.or()?;
+                match self.next(){
+                    Some((Token::RParen, _)) => Ok(inner),
+                    Some((token, at)) => Err(expression_error(at, &format!("unexpected '{}', expected ')' for the '(' at column {}", token.text(), column))),
+                    None => Err(expression_error(column, "unmatched '('")),
+                }
+            },
+            Some((Token::Word(word), column)) => self.condition(&word, column),
+            Some((token, column)) => Err(expression_error(column, &format!("unexpected '{}', expected a condition", token.text()))),
+            None => Err(expression_error(self.end(), "expected a condition at the end")),
+        }
+    }
+
+    fn condition(&mut self, word : &str, column : usize) -> Result<Predicate, StatError>{
+        let Some((Token::Op(op), op_column)) = self.peek().cloned() else{
+            return flag(word).ok_or_else(|| expression_error(column, &format!("unknown condition '{}'", word)));
+        };
+        self.pos += 1;
+        let (value, value_column) = match self.next(){
+            Some((Token::Word(value), value_column)) => (value, value_column),
+            Some((token, at)) => return Err(expression_error(at, &format!("unexpected '{}', expected a value after '{}{}'", token.text(), word, op.symbol()))),
+            None => return Err(expression_error(self.end(), &format!("expected a value after '{}{}'", word, op.symbol()))),
+        };
+        let equality = matches!(op, Op::Eq | Op::Ne);
+        let invalid = |what : &str| expression_error(value_column, &format!("invalid {} '{}'", what, value));
+        let predicate = match word{
+            "type" | "name" | "path" | "user" | "owner" | "group" if !equality => {
+                return Err(expression_error(op_column, &format!("'{}' can only be compared with = or !=", word)));
+            },
+            "type" => Predicate::Type(type_bits(&value).ok_or_else(|| invalid("file type"))?),
+            "name" => Predicate::Name(false, value.clone()),
+            "path" => Predicate::Name(true, value.clone()),
+            "user" | "owner" => Predicate::User(value.clone()),
+            "group" => Predicate::Group(value.clone()),
+            "uid" | "gid" | "links" | "inode" => {
+                let field = match word{
+                    "uid" => Field::Uid,
+                    "gid" => Field::Gid,
+                    "links" => Field::Links,
+                    _ => Field::Inode,
+                };
+                let number = value.parse::<i64>().map_err(|_| invalid("number"))?;
+                return Ok(Predicate::Compare(field, op, Operand::Number(number)));
+            },
+            "size" => return Ok(Predicate::Compare(Field::Size, op, Operand::Number(parse_size(&value).ok_or_else(|| invalid("size"))?))),
+            "mode" | "perm" => {
+                let mode = i64::from_str_radix(&value, 8).ok().filter(|m| (0..=0o7777).contains(m)).ok_or_else(|| invalid("mode"))?;
+                return Ok(Predicate::Compare(Field::Mode, op, Operand::Number(mode)));
+            },
+            "atime" | "mtime" | "ctime" => {
+                let field = match word{
+                    "atime" => Field::Atime,
+                    "mtime" => Field::Mtime,
+                    _ => Field::Ctime,
+                };
+                let operand = match parse_duration(&value){
+                    Some(ago) => Operand::Ago(ago),
+                    None => Operand::Number(parse_timestamp(&value).ok_or_else(|| invalid("time"))?),
+                };
+                return Ok(Predicate::Compare(field, op, operand));
+            },
+            _ => return Err(expression_error(column, &format!("unknown field '{}'", word))),
+        };
+        Ok(if op==Op::Ne { Predicate::Not(Box::new(predicate)) } else { predicate })
+    }
+}
+
+/// 单独的名字
+fn flag(name : &str) -> Option<Predicate>{
+    match name{
+        "device" => Some(Predicate::Device),
+        "setuid" => Some(Predicate::Setuid),
+        "setgid" => Some(Predicate::Setgid),
+        "sticky" => Some(Predicate::Sticky),
+        "exec" => Some(Predicate::Exec),
+        "empty" => Some(Predicate::Empty),
+        _ => type_bits(name).map(Predicate::Type),
+    }
+}
+
+/// 类型名对应的S_IFMT值，名字和 find -type 的字母也可以用
+fn type_bits(name : &str) -> Option<u32>{
+    match name{
+        "file" | "regular" | "f" => Some(S_IFREG),
+        "dir" | "directory" | "d" => Some(S_IFDIR),
+        "link" | "symlink" | "l" => Some(S_IFLNK),
+        "block" | "b" => Some(S_IFBLK),
+        "char" | "c" => Some(S_IFCHR),
+        "fifo" | "pipe" | "p" => Some(S_IFIFO),
+        "socket" | "s" => Some(S_IFSOCK),
+        _ => None,
+    }
+}
+
+/// 带 K M G T P 后缀的大小，可以有小数
+fn parse_size(text : &str) -> Option<i64>{
+    let (number, unit) = match text.char_indices().find(|(_, c)| c.is_ascii_alphabetic()){
+        Some((i, _)) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    };
+    let power = match unit.to_ascii_uppercase().as_str(){
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        "T" | "TB" | "TIB" => 4,
+        "P" | "PB" | "PIB" => 5,
+        _ => return None,
+    };
+    let value = number.parse::<f64>().ok().filter(|v| *v>=0.0)?;
+    Some((value*1024f64.powi(power)) as i64)
+}
+
+/// 带单位的时长，给出往前的秒数；-7d、7d为7天前，+1h为1小时后
+fn parse_duration(text : &str) -> Option<i64>{
+    let unit = match text.chars().last()?{
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        'w' => 86400*7,
+        'y' => 86400*365,
+        _ => return None,
+    };
+    let number = &text[..text.len()-1];
+    let (sign, digits) = match number.strip_prefix('+'){
+        Some(digits) => (-1, digits),
+        None => (1, number.strip_prefix('-').unwrap_or(number)),
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()){
+        return None;
+    }
+    digits.parse::<i64>().ok()?.checked_mul(unit)?.checked_mul(sign)
+}
+
+fn base_name(path : &str) -> &str{
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty(){
+        return path;
+    }
+    trimmed.rsplit('/').next().unwrap_or(trimmed)
+}
+
+/// 只支持 * 和 ? 的通配符匹配
+fn glob_match(pattern : &str, text : &str) -> bool{
+    let pattern : Vec<char> = pattern.chars().collect();
+    let text : Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // 上一个'*'的位置和它当时匹配到的文字位置，失配时让'*'多吃一个字符再试
+    let mut star : Option<(usize, usize)> = None;
+    while t<text.len(){
+        match pattern.get(p){
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            },
+            Some(&c) if c=='?' || c==text[t] => {
+                p += 1;
+                t += 1;
+            },
+            _ => match star{
+                Some((sp, st)) => {
+                    p = sp+1;
+                    t = st+1;
+                    star = Some((sp, st+1));
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c=='*')
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::kstat::get_file_stat;
+
+    const NOW : i64 = 1_700_000_000;
+
+    fn parse(text : &str) -> Predicate{
+        Predicate::parse(text).unwrap_or_else(|e| panic!("{}: {}", text, e))
+    }
+
+    fn error(text : &str) -> String{
+        Predicate::parse(text).err().unwrap().to_string()
+    }
+
+    fn not(p : Predicate) -> Predicate{
+        Predicate::Not(Box::new(p))
+    }
+
+    fn and(a : Predicate, b : Predicate) -> Predicate{
+        Predicate::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a : Predicate, b : Predicate) -> Predicate{
+        Predicate::Or(Box::new(a), Box::new(b))
+    }
+
+    /// 名为name的普通文件，其余字段来自根目录的stat
+    fn file(name : &str, mode : u32, size : i64, mtime : i64) -> FileStat{
+        let mut filestat = get_file_stat(String::from("/"));
+        filestat.filename = name.to_string();
+        filestat.stat.st_mode = mode;
+        filestat.stat.st_size = size;
+        filestat.stat.st_mtime = mtime;
+        filestat
+    }
+
+    #[test]
+    fn tokens(){
+        let tokens : Vec<Token> = tokenize("!(a>=1||b) && c=='x y'").unwrap().into_iter().map(|(t, _)| t).collect();
+        assert_eq!(tokens, [
+            Token::Not, Token::LParen, Token::Word(String::from("a")), Token::Op(Op::Ge), Token::Word(String::from("1")),
+            Token::Or, Token::Word(String::from("b")), Token::RParen, Token::And,
+            Token::Word(String::from("c")), Token::Op(Op::Eq), Token::Word(String::from("x y")),
+        ]);
+        let columns : Vec<usize> = tokenize(" a !=  \"b\"").unwrap().into_iter().map(|(_, c)| c).collect();
+        assert_eq!(columns, [2, 4, 8]);
+    }
+
+    /// && 比 || 结合得紧，! 比 && 结合得紧，括号改变顺序
+    #[test]
+    fn precedence(){
+        assert_eq!(parse("file || dir && empty"), or(parse("file"), and(parse("dir"), parse("empty"))));
+        assert_eq!(parse("(file || dir) && empty"), and(or(parse("file"), parse("dir")), parse("empty")));
+        assert_eq!(parse("!file && dir"), and(not(parse("file")), parse("dir")));
+        assert_eq!(parse("!!file"), not(not(parse("file"))));
+        assert_eq!(parse("uid=1 || uid=2 || uid=3"), or(or(parse("uid=1"), parse("uid=2")), parse("uid=3")));
+    }
+
+    #[test]
+    fn quoting(){
+        assert_eq!(parse("name='a b&&c'"), Predicate::Name(false, String::from("a b&&c")));
+        assert_eq!(parse("path=\"it's\""), Predicate::Name(true, String::from("it's")));
+        assert_eq!(parse("name!=*.txt"), not(Predicate::Name(false, String::from("*.txt"))));
+        assert_eq!(error("name='abc"), "invalid expression: column 6: unterminated quote");
+    }
+
+    #[test]
+    fn sizes(){
+        let size = |text : &str| match parse(&format!("size>{}", text)){
+            Predicate::Compare(Field::Size, Op::Gt, Operand::Number(n)) => n,
+            p => panic!("{:?}", p),
+        };
+        assert_eq!(size("100"), 100);
+        assert_eq!(size("1k"), 1024);
+        assert_eq!(size("1.5M"), 1536*1024);
+        assert_eq!(size("2GiB"), 2<<30);
+        assert_eq!(size("1TB"), 1<<40);
+        assert_eq!(error("size>1x"), "invalid expression: column 6: invalid size '1x'");
+        assert_eq!(error("size>-1"), "invalid expression: column 6: invalid size '-1'");
+    }
+
+    #[test]
+    fn times(){
+        assert_eq!(parse("mtime>7d"), parse("mtime>-7d"));
+        assert_eq!(parse("mtime>7d"), Predicate::Compare(Field::Mtime, Op::Gt, Operand::Ago(7*86400)));
+        assert_eq!(parse("atime<+1h"), Predicate::Compare(Field::Atime, Op::Lt, Operand::Ago(-3600)));
+        assert_eq!(parse("ctime>=@1700000000"), Predicate::Compare(Field::Ctime, Op::Ge, Operand::Number(1700000000)));
+        assert_eq!(parse("mtime<2023-11-14T22:13:20Z"), Predicate::Compare(Field::Mtime, Op::Lt, Operand::Number(1700000000)));
+        assert_eq!(error("mtime>7x"), "invalid expression: column 7: invalid time '7x'");
+    }
+
+    #[test]
+    fn evaluate(){
+        let data = file("/srv/data.txt", libc::S_IFREG | 0o644, 2048, NOW-3*86400);
+        let script = file("/srv/run.sh", libc::S_IFREG | 0o4755, 0, NOW-60);
+        let dir = file("/srv/", libc::S_IFDIR | 0o1777, 4096, NOW);
+        let check = |text : &str, f : &FileStat| parse(text).matches(f, NOW);
+
+        assert!(check("file && size>1k && size<=2k", &data));
+        assert!(check("name=*.txt && path=/srv/*", &data));
+        assert!(check("mtime<2d && mtime>4d", &data));
+        assert!(check(&format!("mtime=@{}", NOW-3*86400), &data));
+        assert!(!check("exec || empty || setuid", &data));
+        assert!(check("exec && empty && setuid && perm=4755", &script));
+        // 比较的是时刻：mtime>2m 为2分钟内修改过
+        assert!(check("mtime>2m && !(mtime>30s)", &script));
+        assert!(check("dir && sticky && name=srv && type=d", &dir));
+        assert!(check("type!=file && !device", &dir));
+    }
+
+    /// 错误给出位置和出错的记号
+    #[test]
+    fn errors_name_the_token(){
+        assert_eq!(error("file dir"), "invalid expression: column 6: unexpected 'dir', expected '&&' or '||'");
+        assert_eq!(error("file &&"), "invalid expression: column 8: expected a condition at the end");
+        assert_eq!(error("file && || dir"), "invalid expression: column 9: unexpected '||', expected a condition");
+        assert_eq!(error("(file dir"), "invalid expression: column 7: unexpected 'dir', expected ')' for the '(' at column 1");
+        assert_eq!(error("(file"), "invalid expression: column 1: unmatched '('");
+        assert_eq!(error("file)"), "invalid expression: column 5: unmatched ')'");
+        assert_eq!(error("size > && file"), "invalid expression: column 8: unexpected '&&', expected a value after 'size>'");
+        assert_eq!(error("size >="), "invalid expression: column 8: expected a value after 'size>='");
+        assert_eq!(error("file & dir"), "invalid expression: column 6: '&' must be doubled");
+        assert_eq!(error("bogus"), "invalid expression: column 1: unknown condition 'bogus'");
+        assert_eq!(error("color=red"), "invalid expression: column 1: unknown field 'color'");
+        assert_eq!(error("type<f"), "invalid expression: column 5: 'type' can only be compared with = or !=");
+        assert_eq!(error("type=x"), "invalid expression: column 6: invalid file type 'x'");
+        assert_eq!(error("perm=0999"), "invalid expression: column 6: invalid mode '0999'");
+    }
+
+    #[test]
+    fn short_condition_prefix(){
+        assert_eq!(Predicate::parse_prefix("file:x}").unwrap(), (parse("file"), 4));
+        assert_eq!(Predicate::parse_prefix("name=':':x}").unwrap(), (parse("name=':'"), 8));
+        assert!(Predicate::parse_prefix("file}:x").is_err());
+        assert!(Predicate::parse_prefix("file").is_err());
+    }
+
+    #[test]
+    fn globs(){
+        assert!(glob_match("*.t?t", "a.txt"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b", "a"));
+        assert!(glob_match("*", ""));
+        assert_eq!(base_name("/a/b/"), "b");
+        assert_eq!(base_name("/"), "/");
+    }
+}
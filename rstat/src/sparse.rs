@@ -0,0 +1,124 @@
+//! 稀疏文件：实际分配的空间、稀疏程度，以及用 lseek(SEEK_DATA/SEEK_HOLE) 得到的数据段和空洞
+//! 不支持SEEK_DATA/SEEK_HOLE的文件系统会把整个文件当作一个数据段，这和内核的通用实现一致
+use std::io;
+
+use crate::kstat::{cpath, stat};
+
+/// st_blocks的单位总是512字节，和st_blksize无关
+pub const BLOCK_UNIT : i64 = 512;
+
+/// 文件中的一段
+pub struct Segment{
+    pub data : bool,    //true为数据，false为空洞
+    pub start : i64,
+    pub end : i64,
+}
+
+/// 实际分配的字节数
+pub fn allocated(filestat : &stat) -> i64{
+    filestat.st_blocks*BLOCK_UNIT
+}
+
+/// 没有分配空间的部分占文件大小的比例，0为完全不稀疏
+/// 分配的空间可能因为预分配或者元数据比文件大小还大，这时也算0
+pub fn sparseness(filestat : &stat) -> f64{
+    if filestat.st_size<=0{
+        return 0.0;
+    }
+    let ratio = 1.0 - allocated(filestat) as f64/filestat.st_size as f64;
+    ratio.max(0.0)
+}
+
+/// 按顺序列出文件中的数据段和空洞
+pub fn segments(filename : &str, follow : bool, size : i64) -> io::Result<Vec<Segment>>{
+    let nofollow = if follow { 0 } else { libc::O_NOFOLLOW };
+    let fd = unsafe{ libc::open(cpath(filename).as_ptr(), libc::O_RDONLY|libc::O_CLOEXEC|nofollow) };
+    if fd==-1{
+        return Err(io::Error::last_os_error());
+    }
+    let res = walk_segments(fd, size);
+    unsafe{
+        libc::close(fd);
+    }
+    res
+}
+
+fn walk_segments(fd : i32, size : i64) -> io::Result<Vec<Segment>>{
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos<size{
+        let data = unsafe{ libc::lseek(fd, pos, libc::SEEK_DATA) };
+        if data==-1{
+            let err = io::Error::last_os_error();
+            // ENXIO表示pos之后再没有数据，剩下的都是空洞
+            if err.raw_os_error()==Some(libc::ENXIO){
+                segments.push(Segment{ data : false, start : pos, end : size });
+                break;
+            }
+            return Err(err);
+        }
+        if data>pos{
+            segments.push(Segment{ data : false, start : pos, end : data });
+        }
+        let hole = unsafe{ libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole==-1{
+            return Err(io::Error::last_os_error());
+        }
+        segments.push(Segment{ data : true, start : data, end : hole });
+        pos = hole;
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::kstat::{get_file_stat, OptionSelected};
+    use std::fs::{self, File};
+    use std::os::unix::fs::FileExt;
+
+    const MIB : i64 = 1024*1024;
+
+    #[test]
+    fn holes_and_allocated_size(){
+        let dir = std::env::temp_dir().join(format!("rstat-test-{}-sparse", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sparse").to_string_lossy().into_owned();
+        let report = dir.join("report").to_string_lossy().into_owned();
+        // 空洞 [0,1M)，数据 [1M,1M+4K)，空洞 [1M+4K,3M)
+        let file = File::create(&path).unwrap();
+        file.set_len(3*MIB as u64).unwrap();
+        file.write_all_at(&[b'x'; 4096], MIB as u64).unwrap();
+        file.sync_all().unwrap();
+        drop(file);
+
+        let filestat = get_file_stat(path.clone());
+        let segments = segments(&path, false, filestat.stat.st_size).unwrap();
+
+        // %b 乘以 %B 就是实际分配的大小，%B 总是512而不是st_blksize
+        let mut opt = OptionSelected::default();
+        let args = ["rstat", "-c", "%b %B", "-o", &report, &path].iter().map(|s| s.to_string()).collect();
+        opt.readoption(args).unwrap();
+        opt.output().unwrap();
+        let text = fs::read_to_string(&report).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(text, format!("{} 512\n", filestat.stat.st_blocks));
+        assert_eq!(allocated(&filestat.stat), filestat.stat.st_blocks*512);
+        assert!(allocated(&filestat.stat)>=4096 && allocated(&filestat.stat)<MIB, "{}", allocated(&filestat.stat));
+        assert!(sparseness(&filestat.stat)>0.6);
+
+        let ranges : Vec<(bool, i64, i64)> = segments.iter().map(|s| (s.data, s.start, s.end)).collect();
+        assert_eq!(ranges, [(false, 0, MIB), (true, MIB, MIB+4096), (false, MIB+4096, 3*MIB)]);
+    }
+
+    #[test]
+    fn sparseness_of_empty_file(){
+        let mut filestat : stat = unsafe{ std::mem::zeroed() };
+        assert_eq!(sparseness(&filestat), 0.0);
+        // 预分配的空间比文件还大时也是0
+        filestat.st_size = 100;
+        filestat.st_blocks = 8;
+        assert_eq!(sparseness(&filestat), 0.0);
+    }
+}
@@ -0,0 +1,223 @@
+//! 默认输出中时间和文件名的写法：--time-style 和 --quoting-style
+//! 名字和 ls(1) 的同名选项一致，只影响给人看的默认输出，-t、-c、--json 不变；
+//! 格式串中的 %{mtime:long-iso} 等也用同样的写法
+use chrono::prelude::*;
+use chrono::SecondsFormat;
+
+use crate::i18n::{Messages, EN};
+use crate::kstat::{get_time_full, get_time_utc2local};
+
+/// 时间的写法
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeStyle{
+    FullIso,            //2022-05-10 16:04:15.123456789 +08:00，默认
+    LongIso,            //2022-05-10 16:04
+    Iso,                //2022-05-10
+    Custom(String),     //+FORMAT，按strftime的写法
+}
+
+impl TimeStyle{
+    pub fn from_name(name : &str) -> Option<Self>{
+        match name{
+            "full-iso" | "default" => Some(TimeStyle::FullIso),
+            "long-iso" => Some(TimeStyle::LongIso),
+            "iso" => Some(TimeStyle::Iso),
+            _ => name.strip_prefix('+').map(|f| TimeStyle::Custom(f.to_string())),
+        }
+    }
+
+    /// offset为时区，例如 +08:00，只有full-iso会用到
+    pub fn format(&self, sec : i64, nsec : i64, offset : &str) -> String{
+        let pattern = match self{
+            TimeStyle::FullIso => return format!("{}.{} {}",get_time_utc2local(sec, nsec),nsec,offset),
+            TimeStyle::LongIso => "%Y-%m-%d %H:%M",
+            TimeStyle::Iso => "%Y-%m-%d",
+            TimeStyle::Custom(pattern) => pattern.as_str(),
+        };
+        match Local.timestamp_opt(sec, nsec.clamp(0, 999_999_999) as u32){
+            chrono::LocalResult::Single(t) => {
+                // 不合法的strftime写法会让chrono在输出时出错，这里退回到原始的秒数
+                let mut text = String::new();
+                match std::fmt::write(&mut text, format_args!("{}",t.format(pattern))){
+                    Ok(()) => text,
+                    Err(_) => sec.to_string(),
+                }
+            },
+            _ => sec.to_string(),
+        }
+    }
+}
+
+/// 格式串中 %{mtime:参数} 这类时间指令的内容，参数不认识时为None
+/// 没有参数时和 get_time_full 相同；epoch为秒数；iso为带时区的ISO 8601（注意和 --time-style=iso 不同）；
+/// ago和age为相对于now的时间，和%F一样不随语言变化；其余参数按 --time-style 的写法，时区按该时刻本地的时区
+pub fn time_directive(sec : i64, nsec : i64, arg : Option<&str>, now : i64) -> Option<String>{
+    let arg = match arg{
+        None => return Some(get_time_full(sec, nsec)),
+        Some("ago") => return Some(relative_time(sec, now, &EN)),
+        Some("age") => return Some(now.saturating_sub(sec).to_string()),
+        Some(arg) => arg,
+    };
+    let local = match Local.timestamp_opt(sec, nsec.clamp(0, 999_999_999) as u32){
+        chrono::LocalResult::Single(t) => t,
+        _ => return Some(sec.to_string()),
+    };
+    match arg{
+        "epoch" => Some(sec.to_string()),
+        "iso" => Some(local.to_rfc3339_opts(SecondsFormat::Secs, false)),
+        _ => TimeStyle::from_name(arg).map(|style| style.format(sec, nsec, &local.offset().to_string())),
+    }
+}
+
+/// 各个单位的秒数，和 Messages::time_units 的顺序一致，一个月按30天、一年按365天算
+const TIME_UNITS : [i64; 6] = [1, 60, 3600, 86400, 86400*30, 86400*365];
+
+/// 相对于now的时间，取不超过时间差的最大单位并向下取整，例如 3 minutes ago；
+/// 将来的时间（例如时钟不准或者被touch到将来的文件）写成 in 3 minutes，不会给出负数
+pub fn relative_time(sec : i64, now : i64, msg : &Messages) -> String{
+    let diff = now.saturating_sub(sec);
+    if diff==0{
+        return msg.just_now.to_string();
+    }
+    let span = diff.saturating_abs();
+    let unit = TIME_UNITS.iter().rposition(|&u| span>=u).unwrap_or(0);
+    let value = span/TIME_UNITS[unit];
+    let (singular, plural) = msg.time_units[unit];
+    let text = msg.fill(if value==1 { singular } else { plural }, &[&value]);
+    msg.fill(if diff>0 { msg.ago } else { msg.later }, &[&text])
+}
+
+/// --now 的取值：@秒数、秒数、RFC 3339，或者本地时间的 2022-05-10 16:04:15、2022-05-10T16:04:15、2022-05-10
+pub fn parse_timestamp(text : &str) -> Option<i64>{
+    let text = text.trim();
+    if let Ok(sec) = text.strip_prefix('@').unwrap_or(text).parse::<i64>(){
+        return Some(sec);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text){
+        return Some(time.timestamp());
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"].iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(text, f).ok())
+        .or_else(|| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0))?;
+    Local.from_local_datetime(&naive).earliest().map(|t| t.timestamp())
+}
+
+/// 文件名的写法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotingStyle{
+    Literal,        //原样输出，默认
+    Shell,          //需要时用单引号括起来，可以直接粘贴到shell中
+    ShellAlways,    //总是用单引号括起来
+    Escape,         //不可打印的字符写成C的转义
+    C,              //C的转义，并用双引号括起来
+}
+
+impl QuotingStyle{
+    pub fn from_name(name : &str) -> Option<Self>{
+        match name{
+            "literal" => Some(QuotingStyle::Literal),
+            "shell" => Some(QuotingStyle::Shell),
+            "shell-always" => Some(QuotingStyle::ShellAlways),
+            "escape" => Some(QuotingStyle::Escape),
+            "c" => Some(QuotingStyle::C),
+            _ => None,
+        }
+    }
+
+    pub fn quote(&self, name : &str) -> String{
+        match self{
+            QuotingStyle::Literal => name.to_string(),
+            QuotingStyle::Shell if !name.is_empty() && name.chars().all(shell_safe) => name.to_string(),
+            QuotingStyle::Shell | QuotingStyle::ShellAlways => format!("'{}'",name.replace('\'', "'\\''")),
+            QuotingStyle::Escape => c_escape(name, false),
+            QuotingStyle::C => format!("\"{}\"",c_escape(name, true)),
+        }
+    }
+}
+
+/// 在shell中不需要引号的字符
+fn shell_safe(c : char) -> bool{
+    c.is_ascii_alphanumeric() || "_-./,:+@%^=".contains(c)
+}
+
+/// 转义'\'和不可打印的字符，quote为true时还要转义双引号；不可打印的字节写成三位八进制
+fn c_escape(name : &str, quote : bool) -> String{
+    let mut res = String::new();
+    for c in name.chars(){
+        match c{
+            '\\' => res.push_str("\\\\"),
+            '"' if quote => res.push_str("\\\""),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            ' ' if !quote => res.push_str("\\ "),
+            c if c.is_control() => {
+                let mut buf = [0u8; 4];
+                for b in c.encode_utf8(&mut buf).bytes(){
+                    res.push_str(&format!("\\{:03o}",b));
+                }
+            },
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::i18n::ZH_CN;
+
+    /// 本地时间对应的秒数，结果和运行时的时区有关
+    fn local(y : i32, m : u32, d : u32, h : u32, min : u32, sec : u32) -> i64{
+        let naive = NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, sec).unwrap();
+        Local.from_local_datetime(&naive).earliest().unwrap().timestamp()
+    }
+
+    #[test]
+    fn parse_now_values(){
+        assert_eq!(parse_timestamp("@0"), Some(0));
+        assert_eq!(parse_timestamp("1700000000"), Some(1700000000));
+        assert_eq!(parse_timestamp(" @-1 "), Some(-1));
+        assert_eq!(parse_timestamp("2024-01-01T00:00:00Z"), Some(1704067200));
+        assert_eq!(parse_timestamp("2024-01-01T08:00:00+08:00"), Some(1704067200));
+        assert_eq!(parse_timestamp("2022-05-10 16:04:15"), Some(local(2022, 5, 10, 16, 4, 15)));
+        assert_eq!(parse_timestamp("2022-05-10T16:04:15"), Some(local(2022, 5, 10, 16, 4, 15)));
+        assert_eq!(parse_timestamp("2022-05-10"), Some(local(2022, 5, 10, 0, 0, 0)));
+    }
+
+    #[test]
+    fn parse_now_rejects(){
+        for text in ["", "@", "yesterday", "2022-13-01", "2022-02-30", "2022-05-10 25:00:00", "2022-05-10T16:04"]{
+            assert_eq!(parse_timestamp(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn relative_times(){
+        let now = 1_000_000_000;
+        assert_eq!(relative_time(now, now, &EN), "just now");
+        assert_eq!(relative_time(now-1, now, &EN), "1 second ago");
+        assert_eq!(relative_time(now-59, now, &EN), "59 seconds ago");
+        assert_eq!(relative_time(now-60, now, &EN), "1 minute ago");
+        assert_eq!(relative_time(now-7199, now, &EN), "1 hour ago");
+        assert_eq!(relative_time(now-86400*2, now, &EN), "2 days ago");
+        assert_eq!(relative_time(now-86400*30, now, &EN), "1 month ago");
+        assert_eq!(relative_time(now-86400*365*3, now, &EN), "3 years ago");
+        assert_eq!(relative_time(now+180, now, &EN), "in 3 minutes");
+        assert_eq!(relative_time(now-180, now, &ZH_CN), "3分钟前");
+        assert_eq!(relative_time(now+1, now, &ZH_CN), "1秒后");
+        // 相差太大时不会溢出
+        assert_eq!(relative_time(i64::MIN, i64::MAX, &EN), format!("{} years ago", i64::MAX/(86400*365)));
+    }
+
+    #[test]
+    fn ago_and_age_directives(){
+        let now = 1_000_000_000;
+        assert_eq!(time_directive(now-90, 0, Some("ago"), now).unwrap(), "1 minute ago");
+        assert_eq!(time_directive(now-90, 0, Some("age"), now).unwrap(), "90");
+        assert_eq!(time_directive(now+5, 0, Some("age"), now).unwrap(), "-5");
+        assert_eq!(time_directive(now, 0, Some("epoch"), now).unwrap(), "1000000000");
+        assert_eq!(time_directive(now, 0, Some("nonsense"), now), None);
+    }
+}
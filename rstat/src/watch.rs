@@ -0,0 +1,103 @@
+//! --watch 模式：不断重新获取文件信息，字段有变化时输出一行
+//! 优先使用inotify等待事件，同时按间隔轮询，这样不会送达事件的文件系统（例如NFS上别的机器做的修改）也能发现变化
+use std::io::{self, Write};
+use std::time::Duration;
+
+use chrono::prelude::*;
+
+use crate::kstat::{cpath, FileStat};
+
+/// 对文件本身的修改
+const FILE_MASK : u32 = libc::IN_ATTRIB | libc::IN_MODIFY | libc::IN_ACCESS | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVE_SELF | libc::IN_DELETE_SELF;
+/// 父目录中的增删改名，文件被rename替换时只有父目录能收到
+const DIR_MASK : u32 = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_MOVED_TO;
+
+/// 监视files中的每一个文件，直到输出出错为止
+/// nofollow为true时监视链接本身而不是链接指向的文件
+pub fn watch<W : Write>(out : &mut W, files : &[String], getter : impl Fn(String) -> FileStat,
+                        nofollow : bool, interval : Duration) -> io::Result<()>{
+    let mut last : Vec<FileStat> = files.iter().cloned().map(&getter).collect();
+    // inotify不可用时（例如达到了实例数上限）就只靠轮询
+    let inotify = Inotify::new();
+
+    loop{
+        if let Some(inotify) = &inotify{
+            // 文件被替换后inode变了，每一轮都重新添加一次，对同一个inode重复添加没有副作用
+            for name in files{
+                inotify.add(name, FILE_MASK, nofollow);
+                inotify.add(&parent_dir(name), DIR_MASK, false);
+            }
+            inotify.wait(interval);
+        }
+        else{
+            std::thread::sleep(interval);
+        }
+
+        for (old, name) in last.iter_mut().zip(files){
+            let new = getter(name.clone());
+            let changes = old.diff(&new);
+            if changes.is_empty(){
+                continue;
+            }
+            let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+            for change in changes{
+                writeln!(out, "{} {}: {}: {} -> {}",now,name,change.field,change.old,change.new)?;
+            }
+            *old = new;
+        }
+        out.flush()?;
+    }
+}
+
+/// 文件所在的目录，没有目录部分时就是当前目录
+fn parent_dir(name : &str) -> String{
+    match std::path::Path::new(name).parent(){
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().into_owned(),
+        _ => String::from("."),
+    }
+}
+
+struct Inotify{
+    fd : i32,
+}
+
+impl Inotify{
+    fn new() -> Option<Self>{
+        let fd = unsafe{ libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd==-1{
+            return None;
+        }
+        Some(Inotify{fd})
+    }
+
+    /// 添加监视，文件暂时不存在时会失败，下一轮再试
+    fn add(&self, name : &str, mask : u32, nofollow : bool){
+        let mask = if nofollow { mask | libc::IN_DONT_FOLLOW } else { mask };
+        unsafe{
+            libc::inotify_add_watch(self.fd, cpath(name).as_ptr(), mask);
+        }
+    }
+
+    /// 等待事件或者超时，然后把已经到达的事件都读掉
+    /// 具体是哪个文件的事件并不重要，反正之后会把所有文件重新检查一遍
+    fn wait(&self, timeout : Duration){
+        let mut pfd = libc::pollfd{ fd : self.fd, events : libc::POLLIN, revents : 0 };
+        let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+        unsafe{
+            if libc::poll(&mut pfd, 1, millis)<=0{
+                return;
+            }
+            let mut buf = [0u8; 4096];
+            while libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len())>0{}
+        }
+    }
+}
+
+impl Drop for Inotify{
+    fn drop(&mut self){
+        unsafe{
+            libc::close(self.fd);
+        }
+    }
+}
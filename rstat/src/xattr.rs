@@ -0,0 +1,117 @@
+//! 扩展属性（xattr）
+//! 不跟随链接时使用 llistxattr/lgetxattr，-L 时使用 listxattr/getxattr
+use std::io;
+
+use crate::kstat::cpath;
+
+/// 列出文件所有扩展属性的名字
+pub fn list(filename : &str, follow : bool) -> io::Result<Vec<String>>{
+    let path = cpath(filename);
+    let call = |buf : *mut libc::c_char, size : usize| unsafe{
+        if follow{
+            libc::listxattr(path.as_ptr(), buf, size)
+        }
+        else{
+            libc::llistxattr(path.as_ptr(), buf, size)
+        }
+    };
+    let buf = read_sized(|buf, size| call(buf.cast(), size))?;
+    // 名字之间以'\0'分隔
+    Ok(buf.split(|&b| b==0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+/// 读取一个扩展属性的值
+pub fn get(filename : &str, name : &str, follow : bool) -> io::Result<Vec<u8>>{
+    let path = cpath(filename);
+    let name = cpath(name);
+    read_sized(|buf, size| unsafe{
+        if follow{
+            libc::getxattr(path.as_ptr(), name.as_ptr(), buf.cast(), size)
+        }
+        else{
+            libc::lgetxattr(path.as_ptr(), name.as_ptr(), buf.cast(), size)
+        }
+    })
+}
+
+/// 列出所有扩展属性和它们的值，读不到值的属性跳过
+pub fn list_with_values(filename : &str, follow : bool) -> io::Result<Vec<(String, Vec<u8>)>>{
+    Ok(list(filename, follow)?.into_iter()
+        .filter_map(|name|{
+            let value = get(filename, &name, follow).ok()?;
+            Some((name, value))
+        })
+        .collect())
+}
+
+/// 先用长度0询问需要的大小再读取，两次调用之间大小变了（返回ERANGE）就重来
+fn read_sized<F>(call : F) -> io::Result<Vec<u8>>
+where
+    F: Fn(*mut u8, usize) -> isize,
+{
+    loop{
+        let size = call(std::ptr::null_mut(), 0);
+        if size<0{
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let ret = call(buf.as_mut_ptr(), buf.len());
+        if ret>=0{
+            buf.truncate(ret as usize);
+            return Ok(buf);
+        }
+        let err = io::Error::last_os_error();
+        if err.raw_os_error()!=Some(libc::ERANGE){
+            return Err(err);
+        }
+    }
+}
+
+/// 值是可打印的文字时原样给出，否则较短的值用0x开头的十六进制，较长的用0s开头的base64（和getfattr相同）
+/// 很多程序写入的字符串带着结尾的'\0'，这种情况也当作文字
+pub fn display_value(value : &[u8]) -> String{
+    let text = value.strip_suffix(&[0]).unwrap_or(value);
+    if let Ok(s) = std::str::from_utf8(text){
+        if !s.chars().any(|c| c.is_control() && c!='\t'){
+            return s.to_string();
+        }
+    }
+    if value.len()<=32{
+        format!("0x{}",crate::digest::to_hex(value))
+    }
+    else{
+        format!("0s{}",base64(value))
+    }
+}
+
+/// 带引号的写法，用于默认输出，和getfattr的写法一致
+pub fn quoted_value(value : &[u8]) -> String{
+    let res = display_value(value);
+    let text = value.strip_suffix(&[0]).unwrap_or(value);
+    if res.as_bytes()==text{
+        format!("\"{}\"",res.replace('\\',"\\\\").replace('"',"\\\""))
+    }
+    else{
+        res
+    }
+}
+
+fn base64(bytes : &[u8]) -> String{
+    const TABLE : &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::with_capacity(bytes.len().div_ceil(3)*4);
+    for chunk in bytes.chunks(3){
+        let n = (chunk[0] as u32)<<16 | (*chunk.get(1).unwrap_or(&0) as u32)<<8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4{
+            if i<=chunk.len(){
+                res.push(TABLE[(n>>(18-6*i) & 63) as usize] as char);
+            }
+            else{
+                res.push('=');
+            }
+        }
+    }
+    res
+}